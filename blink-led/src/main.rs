@@ -1,20 +1,208 @@
 #![no_std]
 #![no_main]
 
+mod morse;
+
+use core::fmt::Write;
 use cortex_m_rt::entry;
+use morse::{MorseBlinker, UNIT_MS};
 use panic_halt as _;
-use stm32f4xx_hal::{pac, prelude::*};
+use stm32f4xx_hal::{
+    pac::{self, TIM5},
+    prelude::*,
+    serial::{Serial, config::Config},
+    timer::CounterUs,
+};
+
+// The LED "breathes": fades up over half the period, then back down
+const BREATHE_PERIOD_MS: u32 = 2_000;
+const BRIGHTNESS_STEPS: u32 = 50; // Duty cycle changes on the way up (and again on the way down)
+const PWM_FREQ_HZ: u32 = 1_000; // Fast enough that the eye can't see it flicker
+
+// Send a digit 1-9 over the serial port to change the speed:
+// digit n breathes once every n * 500 ms
+const PERIOD_STEP_MS: u32 = 500;
+
+// Or press the blue user button (B1 on PC13) to step through these
+const BUTTON_PERIODS_MS: [u32; 4] = [2_000, 1_000, 500, 4_000];
+
+// The button is sampled every BUTTON_SAMPLE_MS, and has to read the same
+// DEBOUNCE_SAMPLES times in a row before a change counts
+const BUTTON_SAMPLE_MS: u32 = 5;
+const DEBOUNCE_SAMPLES: u8 = 4;
+
+// Morse mode (press m, and b to go back to breathing) blinks this message over and over
+// Letters, digits and spaces are sent, anything else is skipped
+const MORSE_MESSAGE: &str = "SOS";
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Breathe,
+    Morse,
+}
+
+// Time between duty cycle changes for a breathe period
+fn step_interval_us(period_ms: u32) -> u32 {
+    period_ms * 1_000 / (2 * BRIGHTNESS_STEPS)
+}
+
+// Restart the step timer for a new interval
+// A length it can't count is reported over the serial port, and the old one carries on
+fn start_step_timer(timer: &mut CounterUs<TIM5>, tx: &mut impl Write, interval_us: u32) {
+    if timer.start(interval_us.micros()).is_err() {
+        let _ = writeln!(tx, "Can't time a {} us step\r", interval_us);
+    }
+}
 
 #[entry]
 fn main() -> ! {
     let dp = pac::Peripherals::take().unwrap();
+
+    // Default clocks (16 MHz internal oscillator) - the UART needs to know them
+    let rcc = dp.RCC.constrain();
+    let clocks = rcc.cfgr.freeze();
+
     let gpioa = dp.GPIOA.split();
-    let mut led = gpioa.pa5.into_push_pull_output();
+    let gpioc = dp.GPIOC.split();
+
+    // B1 pulls PC13 low while pressed
+    let button = gpioc.pc13.into_pull_up_input();
+
+    // PA5 (the Nucleo's user LED) is also TIM2 channel 1, so the timer can
+    // drive the LED directly - the duty cycle sets how bright it looks
+    let (_, (ch1, ..)) = dp.TIM2.pwm_hz(PWM_FREQ_HZ.Hz(), &clocks);
+    let mut led = ch1.with(gpioa.pa5);
+    let max_duty = led.get_max_duty() as u32;
+    led.set_duty(0);
+    led.enable();
+
+    // UART on the Nucleo's USB port, same as the snake game
+    // PA2 = TX, PA3 = RX
+    let uart = Serial::new(
+        dp.USART2,
+        (gpioa.pa2.into_alternate(), gpioa.pa3.into_alternate()),
+        Config::default().baudrate(115200.bps()),
+        &clocks,
+    )
+    .unwrap();
+    let (mut tx, mut rx) = uart.split();
+
+    writeln!(
+        tx,
+        "Press 1-9 to change the breathing speed (1 = fastest)\r"
+    )
+    .unwrap();
+    writeln!(tx, "Press m for Morse code, b to go back to breathing\r").unwrap();
+    writeln!(tx, "Or press the blue button to change the speed\r").unwrap();
+
+    // TIM5 ticks once per brightness step (or once per unit in Morse mode)
+    // It's one of the 32-bit timers - a 150 ms Morse unit is too long for a 16-bit
+    // one counting microseconds (they stop at 65 ms)
+    let mut step_timer = dp.TIM5.counter_us(&clocks);
+    let mut breathe_step_us = step_interval_us(BREATHE_PERIOD_MS);
+    start_step_timer(&mut step_timer, &mut tx, breathe_step_us);
+
+    // TIM4 paces the button samples
+    let mut button_timer = dp.TIM4.counter_us(&clocks);
+    button_timer
+        .start((BUTTON_SAMPLE_MS * 1_000).micros())
+        .unwrap();
+    let mut last_sample = false; // Pressed at the last sample?
+    let mut same_count: u8 = 0; // Samples in a row that read last_sample
+    let mut pressed = false; // The debounced state
+    let mut period_index = 0; // Into BUTTON_PERIODS_MS
+
+    let mut mode = Mode::Breathe;
+    let mut step: u32 = 0; // 0 to BRIGHTNESS_STEPS and back, the ramp position
+    let mut rising = true;
+    let mut morse = MorseBlinker::new(MORSE_MESSAGE);
+    let mut units_left: u32 = 0; // Until the current Morse segment ends
 
     loop {
-        led.set_high();
-        cortex_m::asm::delay(2_000_000); // ~0.25 seconds
-        led.set_low();
-        cortex_m::asm::delay(2_000_000); // ~0.25 seconds
+        // Check for a new speed or mode (anything else is ignored)
+        match rx.read() {
+            Ok(byte @ b'1'..=b'9') => {
+                let period_ms = (byte - b'0') as u32 * PERIOD_STEP_MS;
+                breathe_step_us = step_interval_us(period_ms);
+                if mode == Mode::Breathe {
+                    start_step_timer(&mut step_timer, &mut tx, breathe_step_us);
+                }
+
+                writeln!(tx, "Breathing every {} ms\r", period_ms).unwrap();
+            }
+            Ok(b'm') if mode != Mode::Morse => {
+                mode = Mode::Morse;
+                morse = MorseBlinker::new(MORSE_MESSAGE);
+                units_left = 0;
+                led.set_duty(0);
+                start_step_timer(&mut step_timer, &mut tx, UNIT_MS * 1_000);
+
+                writeln!(tx, "Sending \"{}\" in Morse code\r", MORSE_MESSAGE).unwrap();
+            }
+            Ok(b'b') if mode != Mode::Breathe => {
+                mode = Mode::Breathe;
+                step = 0;
+                rising = true;
+                start_step_timer(&mut step_timer, &mut tx, breathe_step_us);
+
+                writeln!(tx, "Breathing\r").unwrap();
+            }
+            _ => {}
+        }
+
+        // Debounce the button, and act only on the press (not while it's held)
+        if button_timer.wait().is_ok() {
+            let sample = button.is_low();
+            if sample == last_sample {
+                same_count = same_count.saturating_add(1);
+            } else {
+                last_sample = sample;
+                same_count = 1;
+            }
+
+            if same_count >= DEBOUNCE_SAMPLES && sample != pressed {
+                pressed = sample;
+
+                if pressed {
+                    period_index = (period_index + 1) % BUTTON_PERIODS_MS.len();
+                    let period_ms = BUTTON_PERIODS_MS[period_index];
+                    breathe_step_us = step_interval_us(period_ms);
+                    if mode == Mode::Breathe {
+                        start_step_timer(&mut step_timer, &mut tx, breathe_step_us);
+                    }
+
+                    writeln!(tx, "Breathing every {} ms\r", period_ms).unwrap();
+                }
+            }
+        }
+
+        if step_timer.wait().is_err() {
+            continue;
+        }
+
+        match mode {
+            Mode::Breathe => {
+                if rising {
+                    step += 1;
+                    rising = step < BRIGHTNESS_STEPS;
+                } else {
+                    step -= 1;
+                    rising = step == 0;
+                }
+
+                // Eyes notice changes in dim light much more, so square the ramp
+                // to spend more of the time near off
+                let duty = max_duty * step * step / (BRIGHTNESS_STEPS * BRIGHTNESS_STEPS);
+                led.set_duty(duty as u16);
+            }
+            Mode::Morse => {
+                units_left = units_left.saturating_sub(1);
+                if units_left == 0 {
+                    let (on, length) = morse.next_segment();
+                    led.set_duty(if on { max_duty as u16 } else { 0 });
+                    units_left = length;
+                }
+            }
+        }
     }
 }
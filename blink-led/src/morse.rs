@@ -0,0 +1,127 @@
+// Morse code for blink-led - turns a message into LED on/off times
+// Everything is measured in units (one dot): a dash is 3, the gap inside a
+// letter is 1, between letters 3 and between words 7
+
+// Length of one unit, sets how fast the message goes out
+pub const UNIT_MS: u32 = 150;
+
+// Dots and dashes for a letter or digit (None if it has no Morse code)
+fn morse_pattern(c: u8) -> Option<&'static [u8]> {
+    let pattern: &'static [u8] = match c.to_ascii_uppercase() {
+        b'A' => b".-",
+        b'B' => b"-...",
+        b'C' => b"-.-.",
+        b'D' => b"-..",
+        b'E' => b".",
+        b'F' => b"..-.",
+        b'G' => b"--.",
+        b'H' => b"....",
+        b'I' => b"..",
+        b'J' => b".---",
+        b'K' => b"-.-",
+        b'L' => b".-..",
+        b'M' => b"--",
+        b'N' => b"-.",
+        b'O' => b"---",
+        b'P' => b".--.",
+        b'Q' => b"--.-",
+        b'R' => b".-.",
+        b'S' => b"...",
+        b'T' => b"-",
+        b'U' => b"..-",
+        b'V' => b"...-",
+        b'W' => b".--",
+        b'X' => b"-..-",
+        b'Y' => b"-.--",
+        b'Z' => b"--..",
+        b'0' => b"-----",
+        b'1' => b".----",
+        b'2' => b"..---",
+        b'3' => b"...--",
+        b'4' => b"....-",
+        b'5' => b".....",
+        b'6' => b"-....",
+        b'7' => b"--...",
+        b'8' => b"---..",
+        b'9' => b"----.",
+        _ => return None,
+    };
+    Some(pattern)
+}
+
+// Walks through a message one dot/dash at a time, looping at the end
+// Each call to next_segment gives how long the LED is on (or off) in units:
+// dot 1, dash 3, gap 1 inside a letter, 3 between letters, 7 between words
+pub struct MorseBlinker {
+    message: &'static [u8],
+    position: usize, // Character being sent
+    element: usize,  // Dot or dash within it
+    led_on: bool,    // Whether the last segment lit the LED
+}
+
+impl MorseBlinker {
+    pub fn new(message: &'static str) -> Self {
+        MorseBlinker {
+            message: message.as_bytes(),
+            position: 0,
+            element: 0,
+            led_on: false,
+        }
+    }
+
+    // Move `position` forward to the next character with a Morse code, returns true
+    // if a space (or the end of the message) was passed on the way
+    fn skip_to_letter(&mut self) -> bool {
+        let mut word_ended = false;
+
+        for _ in 0..=self.message.len() {
+            match self.message.get(self.position) {
+                Some(&c) if morse_pattern(c).is_some() => return word_ended,
+                Some(&b' ') | None => word_ended = true,
+                Some(_) => {} // Can't be sent - skip it
+            }
+
+            if self.position >= self.message.len() {
+                self.position = 0;
+            } else {
+                self.position += 1;
+            }
+        }
+
+        true // Nothing in the message can be sent
+    }
+
+    // Returns (LED on?, length in units) for the next stretch of the message
+    pub fn next_segment(&mut self) -> (bool, u32) {
+        self.skip_to_letter();
+        let Some(pattern) = self
+            .message
+            .get(self.position)
+            .and_then(|&c| morse_pattern(c))
+        else {
+            return (false, 7); // Nothing to send, stay dark
+        };
+
+        if !self.led_on {
+            // Light up for the next dot or dash
+            self.led_on = true;
+            let length = if pattern[self.element] == b'-' { 3 } else { 1 };
+            return (true, length);
+        }
+
+        // Dark gap after the dot or dash
+        self.led_on = false;
+        self.element += 1;
+        if self.element < pattern.len() {
+            return (false, 1); // More of this letter to come
+        }
+
+        self.element = 0;
+        self.position += 1;
+        if self.skip_to_letter() {
+            (false, 7) // End of a word
+        } else {
+            (false, 3) // Next letter
+        }
+    }
+}
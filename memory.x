@@ -2,6 +2,7 @@ MEMORY
 {
   /* NOTE 1 K = 1 KiByte = 1024 bytes */
   /* STM32F446RE has 512K flash, 128K RAM */
-  FLASH : ORIGIN = 0x08000000, LENGTH = 512K
+  /* The last 128K flash sector (0x08060000) is kept free for the snake high score */
+  FLASH : ORIGIN = 0x08000000, LENGTH = 384K
   RAM : ORIGIN = 0x20000000, LENGTH = 128K
 }
\ No newline at end of file
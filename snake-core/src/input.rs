@@ -0,0 +1,304 @@
+// Turning keys into game inputs - the firmware reads the UART, buttons and
+// joystick, and every source hands out GameInputs through InputSource, so the
+// game tick doesn't care where a turn came from. The key decoding lives here
+// rather than in the firmware so it can be tested on the computer.
+
+use crate::Direction;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum GameInput {
+    Direction(Direction),
+    Player2Direction(Direction), // Arrow keys while both players share the keyboard
+    Restart,
+    Pause,
+    Quit,
+    ToggleWrap,
+    NextLevel,
+    ToggleAutopilot,
+    ToggleAssist,
+    Undo,  // Take back the last move, even the one that ended the game
+    Menu,  // Only does something on the game over screen
+    Other, // Any other key - still counts for "press any key"
+}
+
+pub trait InputSource {
+    // The next input waiting, or None once there's nothing left for now
+    fn poll(&mut self) -> Option<GameInput>;
+}
+
+// Where we are inside an arrow key escape sequence
+#[derive(Clone, Copy, PartialEq)]
+enum EscapeState {
+    Idle,    // Normal keys
+    Escape,  // Got ESC (0x1b)
+    Bracket, // Got ESC [
+}
+
+// Turns arrow key sequences (ESC [ A/B/C/D) into the matching w/a/s/d key
+// Bytes arrive one at a time, so the partial sequence is kept between reads
+pub struct ArrowKeyParser {
+    state: EscapeState,
+    stale: bool,         // A whole frame went by without the sequence finishing
+    pub was_arrow: bool, // The last key returned came from an arrow key, not a letter
+}
+
+impl ArrowKeyParser {
+    fn new() -> Self {
+        ArrowKeyParser {
+            state: EscapeState::Idle,
+            stale: false,
+            was_arrow: false,
+        }
+    }
+
+    // Feed in a received byte, returns the key to act on (if any)
+    pub fn feed(&mut self, byte: u8) -> Option<u8> {
+        self.stale = false;
+        self.was_arrow = self.state == EscapeState::Bracket;
+
+        match (self.state, byte) {
+            (_, 0x1b) => {
+                self.state = EscapeState::Escape;
+                None
+            }
+            (EscapeState::Escape, b'[') => {
+                self.state = EscapeState::Bracket;
+                None
+            }
+            (EscapeState::Bracket, final_byte) => {
+                self.state = EscapeState::Idle;
+                match final_byte {
+                    b'A' => Some(b'w'), // Up
+                    b'B' => Some(b's'), // Down
+                    b'C' => Some(b'd'), // Right
+                    b'D' => Some(b'a'), // Left
+                    _ => None,          // Some other sequence - ignore it
+                }
+            }
+            // Lone ESC followed by a normal key - drop the ESC
+            (_, other) => {
+                self.state = EscapeState::Idle;
+                Some(other)
+            }
+        }
+    }
+
+    // Call at the end of every frame so a lone ESC doesn't hang around forever
+    // A real sequence arrives within a fraction of a millisecond, so anything
+    // still unfinished after a full frame was just the Escape key
+    pub fn end_frame(&mut self) {
+        if self.state == EscapeState::Idle {
+            return;
+        }
+
+        if self.stale {
+            self.state = EscapeState::Idle;
+            self.stale = false;
+        } else {
+            self.stale = true;
+        }
+    }
+}
+
+// Keys typed into a serial terminal (queued up by its UART's interrupt)
+pub struct UartInput {
+    pub arrow_keys: ArrowKeyParser, // The menu reads keys through this too
+    next_byte: fn() -> Option<u8>,  // Takes the oldest byte from the UART's queue
+    // Two players on one keyboard - w/a/s/d steer player one, the arrow keys player two
+    pub shared_keyboard: bool,
+}
+
+impl UartInput {
+    pub fn new(next_byte: fn() -> Option<u8>) -> Self {
+        UartInput {
+            arrow_keys: ArrowKeyParser::new(),
+            next_byte,
+            shared_keyboard: false,
+        }
+    }
+}
+
+impl InputSource for UartInput {
+    fn poll(&mut self) -> Option<GameInput> {
+        // Keep going until a byte finishes a key (arrow keys take three)
+        while let Some(byte) = (self.next_byte)() {
+            let Some(key) = self.arrow_keys.feed(byte) else {
+                continue;
+            };
+
+            return Some(key_input(
+                key,
+                self.shared_keyboard && self.arrow_keys.was_arrow,
+            ));
+        }
+
+        None
+    }
+}
+
+// What a whole key does (arrow keys come in as w/a/s/d)
+// `player2` sends the turns to player two instead
+fn key_input(key: u8, player2: bool) -> GameInput {
+    // Which snake a turn is for
+    let steer = if player2 {
+        GameInput::Player2Direction
+    } else {
+        GameInput::Direction
+    };
+
+    match key {
+        b'w' => steer(Direction::Up),
+        b'a' => steer(Direction::Left),
+        b's' => steer(Direction::Down),
+        b'd' => steer(Direction::Right),
+        b'r' => GameInput::Restart,
+        b'p' | b' ' => GameInput::Pause,
+        b'q' => GameInput::Quit,
+        b't' => GameInput::ToggleWrap,
+        b'l' => GameInput::NextLevel,
+        b'c' => GameInput::ToggleAutopilot,
+        b'h' => GameInput::ToggleAssist,
+        b'u' => GameInput::Undo,
+        b'm' => GameInput::Menu,
+        _ => GameInput::Other,
+    }
+}
+
+// Plays back a fixed list of inputs, one per poll - stands in for a keyboard in tests
+pub struct ScriptedInput {
+    script: &'static [GameInput],
+    next: usize,
+}
+
+impl ScriptedInput {
+    pub fn new(script: &'static [GameInput]) -> Self {
+        ScriptedInput { script, next: 0 }
+    }
+}
+
+impl InputSource for ScriptedInput {
+    fn poll(&mut self) -> Option<GameInput> {
+        let input = self.script.get(self.next).copied();
+        if input.is_some() {
+            self.next += 1;
+        }
+        input
+    }
+}
+
+// Run on the computer, not the board (see the tests in lib.rs)
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+    use std::vec::Vec;
+
+    std::thread_local! {
+        // Bytes "received" by the UartInput under test (each test runs on its own thread)
+        static RECEIVED: RefCell<VecDeque<u8>> = const { RefCell::new(VecDeque::new()) };
+    }
+
+    fn next_test_byte() -> Option<u8> {
+        RECEIVED.with(|received| received.borrow_mut().pop_front())
+    }
+
+    // Type `bytes`, then read out every input they make
+    fn type_keys(input: &mut UartInput, bytes: &[u8]) -> Vec<GameInput> {
+        RECEIVED.with(|received| received.borrow_mut().extend(bytes));
+        core::iter::from_fn(|| input.poll()).collect()
+    }
+
+    #[test]
+    fn arrow_keys_come_out_as_turns() {
+        let mut input = UartInput::new(next_test_byte);
+
+        let inputs = type_keys(&mut input, b"\x1b[A\x1b[B\x1b[C\x1b[Dw");
+
+        assert_eq!(
+            inputs,
+            [
+                GameInput::Direction(Direction::Up),
+                GameInput::Direction(Direction::Down),
+                GameInput::Direction(Direction::Right),
+                GameInput::Direction(Direction::Left),
+                GameInput::Direction(Direction::Up),
+            ]
+        );
+    }
+
+    #[test]
+    fn half_a_sequence_waits_for_the_rest() {
+        let mut input = UartInput::new(next_test_byte);
+
+        // Split across two reads, like bytes arriving either side of a frame
+        assert_eq!(type_keys(&mut input, b"\x1b["), []);
+        assert_eq!(
+            type_keys(&mut input, b"D"),
+            [GameInput::Direction(Direction::Left)]
+        );
+    }
+
+    #[test]
+    fn a_lone_escape_is_cleared_after_a_frame() {
+        let mut keys = ArrowKeyParser::new();
+        assert_eq!(keys.feed(0x1b), None);
+
+        // Still waiting after one frame, given up on after the next
+        keys.end_frame();
+        assert!(keys.state == EscapeState::Escape);
+        keys.end_frame();
+        assert!(keys.state == EscapeState::Idle);
+
+        // So a [ typed later is just a key
+        assert_eq!(keys.feed(b'['), Some(b'['));
+    }
+
+    #[test]
+    fn escape_then_a_letter_gives_the_letter() {
+        let mut input = UartInput::new(next_test_byte);
+
+        assert_eq!(type_keys(&mut input, b"\x1br"), [GameInput::Restart]);
+        assert!(!input.arrow_keys.was_arrow);
+    }
+
+    #[test]
+    fn a_shared_keyboard_sends_the_arrow_keys_to_player_two() {
+        let mut input = UartInput::new(next_test_byte);
+        input.shared_keyboard = true;
+
+        let inputs = type_keys(&mut input, b"d\x1b[Aq");
+
+        assert_eq!(
+            inputs,
+            [
+                GameInput::Direction(Direction::Right),
+                GameInput::Player2Direction(Direction::Up),
+                GameInput::Quit,
+            ]
+        );
+
+        // Without it the arrows steer player one
+        input.shared_keyboard = false;
+        assert_eq!(
+            type_keys(&mut input, b"\x1b[A"),
+            [GameInput::Direction(Direction::Up)]
+        );
+    }
+
+    #[test]
+    fn a_script_plays_back_in_order() {
+        static SCRIPT: [GameInput; 3] = [
+            GameInput::Direction(Direction::Down),
+            GameInput::Pause,
+            GameInput::Undo,
+        ];
+        let mut input = ScriptedInput::new(&SCRIPT);
+
+        let played: Vec<_> = core::iter::from_fn(|| input.poll()).collect();
+
+        assert_eq!(played, SCRIPT);
+        assert_eq!(input.poll(), None); // And stays finished
+    }
+}
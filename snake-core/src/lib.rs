@@ -0,0 +1,2264 @@
+#![no_std]
+
+// Snake game rules, independent of any hardware.
+// Nothing in here touches a peripheral, so it also builds for the host.
+// The firmware in snake-game does all the hardware work: it reads input
+// (UART, buttons), calls move_snake once per timer tick and draws `board`.
+
+use heapless::Deque;
+
+mod input;
+pub use input::{ArrowKeyParser, GameInput, InputSource, ScriptedInput, UartInput};
+mod save;
+pub use save::MAX_SAVE_SIZE;
+mod undo;
+
+// Game event logging over RTT with the defmt feature, compiled out without it
+macro_rules! log_info {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "defmt")]
+        defmt::info!($($arg)*);
+    };
+}
+macro_rules! log_debug {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "defmt")]
+        defmt::debug!($($arg)*);
+    };
+}
+
+// Game constants
+pub const BOARD_WIDTH: usize = 20;
+pub const BOARD_HEIGHT: usize = 15;
+pub const MAX_SNAKE_LENGTH: usize = 100;
+pub const MAX_FOOD: usize = 3; // Food items on the board at once
+
+// Bonus fruit - shows up every BONUS_EVERY_FOODS pellets, gone after BONUS_LIFETIME_STEPS
+const BONUS_EVERY_FOODS: u32 = 10;
+const BONUS_LIFETIME_STEPS: u32 = 30;
+const BONUS_POINTS: u32 = 50;
+const BONUS_WARNING_STEPS: u32 = 8; // The renderers flash it for its last few steps
+
+// Combo - food eaten within COMBO_WINDOW_STEPS of the last one scores 2x, 3x, ... up to MAX_COMBO
+const FOOD_POINTS: u32 = 10;
+const COMBO_WINDOW_STEPS: u32 = 15;
+const MAX_COMBO: u32 = 5;
+
+// Poison pellet - each pellet eaten has a POISON_CHANCE_PERCENT chance of bringing one out,
+// gone after POISON_LIFETIME_STEPS
+// Eating it costs POISON_PENALTY points and POISON_SHRINK segments (never below the start length)
+const POISON_CHANCE_PERCENT: u32 = 25;
+const POISON_LIFETIME_STEPS: u32 = 60;
+const POISON_PENALTY: u32 = 20;
+const POISON_SHRINK: usize = 2;
+
+// Shrinking mode - every SHRINK_EVERY_FOODS pellets the walls close in by one ring,
+// until the inside is only as high as the snake was long at the start (see max_inset)
+const SHRINK_EVERY_FOODS: u32 = 5;
+
+// Score graph on the game over screen - player one's score every SCORE_SAMPLE_STEPS
+// steps, keeping the newest SCORE_HISTORY_SIZE of them
+pub const SCORE_HISTORY_SIZE: usize = 40;
+const SCORE_SAMPLE_STEPS: u32 = 25;
+
+// Levels - every LEVEL_UP_POINTS scored moves on to the next layout
+const LEVEL_UP_POINTS: u32 = 100;
+
+// Snake start - from the middle of the board, with room to react before a wall
+// (GameConfig picks the length and direction)
+const START_LENGTH: usize = 3;
+const START_HEAD: Position = Position {
+    x: BOARD_WIDTH / 2,
+    y: BOARD_HEIGHT / 2,
+};
+const START_CLEARANCE: usize = 3; // Clear cells needed in front of the head
+
+// Player two starts heading left along row 12, which is open in every layout
+// (further left if a long snake wouldn't fit)
+const START_HEAD_2: Position = Position { x: 9, y: 12 };
+const MAX_FOOD_ATTEMPTS: usize = 32; // Random tries before falling back to a scan
+const DIRECTION_QUEUE_SIZE: usize = 4; // Turns that can be pressed ahead of the snake
+
+// Seconds of countdown before a new game starts moving - shown as 3, 2, 1, GO!
+pub const COUNTDOWN_START: u8 = 4;
+
+// Game speed - time between steps starts at the difficulty's start_step_ms and
+// drops linearly by its step_ms_per_food for every food eaten, until it reaches MIN_STEP_MS
+pub const MIN_STEP_MS: u32 = 80; // Fastest the game gets, keeps it playable
+
+// A level layout, one string per board row: '#' is a wall, anything else is open
+// The border is always a wall, whatever the layout says
+type Layout = [&'static str; BOARD_HEIGHT];
+
+// Built-in board layouts
+// Each level uses the next layout, starting over after the last one
+// Keep the ring just inside the border and the start row clear - the demo drives around it
+const LAYOUTS: [Layout; 4] = [
+    // 0: Open box
+    [
+        "####################",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "####################",
+    ],
+    // 1: Two long bars across the top and bottom
+    [
+        "####################",
+        "#..................#",
+        "#..................#",
+        "#...############...#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#...############...#",
+        "#..................#",
+        "#..................#",
+        "####################",
+    ],
+    // 2: Four pillars
+    [
+        "####################",
+        "#..................#",
+        "#..................#",
+        "#...#..........#...#",
+        "#...#..........#...#",
+        "#...#..........#...#",
+        "#..................#",
+        "#..................#",
+        "#..................#",
+        "#...#..........#...#",
+        "#...#..........#...#",
+        "#...#..........#...#",
+        "#..................#",
+        "#..................#",
+        "####################",
+    ],
+    // 3: Maze
+    [
+        "####################",
+        "#..................#",
+        "#..................#",
+        "#..###.######.###..#",
+        "#..#............#..#",
+        "#..#............#..#",
+        "#.....########.....#",
+        "#..................#",
+        "#.....########.....#",
+        "#..#............#..#",
+        "#..#............#..#",
+        "#..###.######.###..#",
+        "#..................#",
+        "#..................#",
+        "####################",
+    ],
+];
+pub const NUM_LAYOUTS: usize = LAYOUTS.len();
+
+// Every layout row has to be exactly BOARD_WIDTH long - checked at compile time
+const _: () = {
+    let mut layout = 0;
+    while layout < NUM_LAYOUTS {
+        let mut row = 0;
+        while row < BOARD_HEIGHT {
+            assert!(LAYOUTS[layout][row].len() == BOARD_WIDTH);
+            row += 1;
+        }
+        layout += 1;
+    }
+};
+
+// How fast the snake starts out
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Difficulty {
+    Slow,
+    Medium,
+    Fast,
+}
+
+impl Difficulty {
+    // Time between steps at the start of a game
+    fn start_step_ms(self) -> u32 {
+        match self {
+            Difficulty::Slow => 300,
+            Difficulty::Medium => 200,
+            Difficulty::Fast => 120,
+        }
+    }
+
+    // How much quicker each food eaten makes the steps - harder ramps up faster too
+    fn step_ms_per_food(self) -> u32 {
+        match self {
+            Difficulty::Slow => 3,
+            Difficulty::Medium => 5,
+            Difficulty::Fast => 8,
+        }
+    }
+
+    // Name for the status line
+    pub fn name(self) -> &'static [u8] {
+        match self {
+            Difficulty::Slow => b"Slow",
+            Difficulty::Medium => b"Medium",
+            Difficulty::Fast => b"Fast",
+        }
+    }
+}
+
+// Game cell types
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Cell {
+    Empty,
+    Wall,
+    Snake,
+    SnakeHead,
+    Snake2, // Player two, in two-player mode
+    Snake2Head,
+    Food,
+    Bonus,
+    Poison,
+    Portal,
+}
+
+// Position on the game board
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct Position {
+    pub x: usize,
+    pub y: usize,
+}
+
+// Snake movement direction
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl Direction {
+    // The cell `steps` along from `pos` this way (negative goes back), None if that's off the board
+    fn offset(self, pos: Position, steps: isize) -> Option<Position> {
+        let (dx, dy) = match self {
+            Direction::Up => (0, -1),
+            Direction::Down => (0, 1),
+            Direction::Left => (-1, 0),
+            Direction::Right => (1, 0),
+        };
+        let x = pos.x.checked_add_signed(dx * steps)?;
+        let y = pos.y.checked_add_signed(dy * steps)?;
+        (x < BOARD_WIDTH && y < BOARD_HEIGHT).then_some(Position { x, y })
+    }
+
+    fn is_horizontal(self) -> bool {
+        matches!(self, Direction::Left | Direction::Right)
+    }
+}
+
+// How each game starts out, picked when the game is made (see GameState::with_config)
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct GameConfig {
+    // Segments to start with - clamped so the snake, and room in front of it, fits inside the walls
+    pub initial_length: usize,
+    pub start_direction: Direction, // Player one's (player two always heads left)
+    // Board size, walls included - the board is still a fixed size array, so for now
+    // anything but BOARD_WIDTH x BOARD_HEIGHT is turned away
+    pub board_width: usize,
+    pub board_height: usize,
+}
+
+impl Default for GameConfig {
+    fn default() -> Self {
+        GameConfig {
+            initial_length: START_LENGTH,
+            start_direction: Direction::Right,
+            board_width: BOARD_WIDTH,
+            board_height: BOARD_HEIGHT,
+        }
+    }
+}
+
+// What a call to move_snake did, so the firmware can react (sound effects etc.)
+#[derive(Clone, Copy, PartialEq, Debug)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum StepEvent {
+    Nothing, // Paused, stalled at a wall in assist mode, or the game had already ended
+    Moved,
+    Ate,      // Food or bonus fruit
+    Poisoned, // Ate poison and shrank
+    Died,     // In two-player mode, see winner() for who
+    Won,
+}
+
+// Software pseudo-random number generator (32-bit xorshift)
+// Visits every non-zero u32 before repeating, so the period is 2^32 - 1.
+// Zero is the one stuck state, so it is never allowed as a seed.
+// The same seed always gives the same numbers, so a game can be replayed from its seed.
+#[derive(Clone)]
+pub struct Rng {
+    state: u32,
+}
+
+impl Rng {
+    pub fn new(seed: u32) -> Self {
+        Rng {
+            // Any non-zero constant works here
+            state: if seed == 0 { 0x2545_F491 } else { seed },
+        }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        self.state = x;
+        x
+    }
+
+    // A number from 0 up to (not including) `max`, which must be above 0
+    // The modulo leans very slightly towards small numbers, nothing a board this size notices
+    pub fn next_range(&mut self, max: usize) -> usize {
+        self.next_u32() as usize % max
+    }
+}
+
+// One snake, plus the turns queued up for it
+#[derive(Clone)]
+struct Snake {
+    // Segments, head at the front - a ring buffer, so a step only
+    // touches the two ends instead of shifting every segment along
+    body: Deque<Position, MAX_SNAKE_LENGTH>,
+    direction: Direction, // Current movement direction
+
+    // Pending turns (ring buffer), one is taken per step
+    direction_queue: [Direction; DIRECTION_QUEUE_SIZE],
+    queue_start: usize, // Index of the oldest queued turn
+    queue_len: usize,   // Number of queued turns
+}
+
+impl Snake {
+    fn new() -> Self {
+        Snake {
+            body: Deque::new(),
+            direction: Direction::Right,
+            direction_queue: [Direction::Right; DIRECTION_QUEUE_SIZE],
+            queue_start: 0,
+            queue_len: 0,
+        }
+    }
+
+    // The snake is never empty while it's playing, so there's always a head
+    fn head(&self) -> Position {
+        *self.body.front().unwrap()
+    }
+
+    // Lay out `length` segments in a line, `head` first and the tail trailing
+    // behind it, with no turns queued (stops short at the edge of the board)
+    fn lay_out(&mut self, head: Position, direction: Direction, length: usize) {
+        self.direction = direction;
+        self.queue_len = 0;
+        self.body.clear();
+        for i in 0..length.min(MAX_SNAKE_LENGTH) {
+            match direction.offset(head, -(i as isize)) {
+                Some(segment) => {
+                    let _ = self.body.push_back(segment);
+                }
+                None => break,
+            }
+        }
+    }
+
+    // Queue a turn for a later step (ignored if the queue is full)
+    fn enqueue_direction(&mut self, direction: Direction) {
+        if self.queue_len == DIRECTION_QUEUE_SIZE {
+            return;
+        }
+
+        let index = (self.queue_start + self.queue_len) % DIRECTION_QUEUE_SIZE;
+        self.direction_queue[index] = direction;
+        self.queue_len += 1;
+    }
+
+    // Pop queued turns until one actually turns the snake
+    // Each is checked against the heading at that moment, not when it was queued,
+    // so a reversal can't sneak in behind an earlier queued turn
+    fn apply_queued_direction(&mut self) {
+        while self.queue_len > 0 {
+            let direction = self.direction_queue[self.queue_start];
+            self.queue_start = (self.queue_start + 1) % DIRECTION_QUEUE_SIZE;
+            self.queue_len -= 1;
+
+            if self.change_direction(direction) {
+                return;
+            }
+        }
+    }
+
+    // The heading the next step will take - the first queued turn that really turns
+    // the snake, as apply_queued_direction finds it, but without taking anything off the queue
+    fn next_direction(&self) -> Direction {
+        (0..self.queue_len)
+            .map(|i| self.direction_queue[(self.queue_start + i) % DIRECTION_QUEUE_SIZE])
+            .find(|&direction| self.can_turn(direction))
+            .unwrap_or(self.direction)
+    }
+
+    // Is `new_direction` a turn? Not straight back, and not the way it's already going
+    fn can_turn(&self, new_direction: Direction) -> bool {
+        // Prevent snake from reversing into itself
+        let opposite = match self.direction {
+            Direction::Up => Direction::Down,
+            Direction::Down => Direction::Up,
+            Direction::Left => Direction::Right,
+            Direction::Right => Direction::Left,
+        };
+
+        new_direction != opposite && new_direction != self.direction
+    }
+
+    // Change direction (with validation)
+    // Returns true if the snake turned
+    fn change_direction(&mut self, new_direction: Direction) -> bool {
+        if !self.can_turn(new_direction) {
+            return false;
+        }
+
+        log_debug!("turned {}", new_direction);
+        self.direction = new_direction;
+        true
+    }
+}
+
+// Main game state
+// Clone is for the renderers, which draw from a copy so the game isn't held up
+#[derive(Clone)]
+pub struct GameState {
+    // Game board - 2D array of cells
+    pub board: [[Cell; BOARD_WIDTH]; BOARD_HEIGHT],
+
+    // Player one's snake, and player two's (empty unless it's a two-player game)
+    snakes: [Snake; 2],
+
+    // Food position
+    food_positions: [Position; MAX_FOOD],
+    food_count: usize, // Only drops below MAX_FOOD if the board runs out of room
+    foods_eaten: u32,  // Regular food eaten this game
+
+    // Score multiplier for food eaten in quick succession (1 = no combo)
+    pub combo: u32,
+    ticks_since_last_food: u32, // Steps since food was last eaten
+
+    // Bonus fruit and the steps it has left before disappearing
+    bonus: Option<(Position, u32)>,
+
+    // Same for the poison pellet
+    poison: Option<(Position, u32)>,
+
+    // Linked portal pair - stepping into one comes out next to the other
+    portals: Option<[Position; 2]>,
+
+    // Game status (read by the renderers)
+    pub score: u32,         // Player one's score
+    pub player2_score: u32, // Player two's, in two-player mode
+    pub high_score: u32,    // Best score so far - the firmware saves and loads this
+    pub game_over: bool,
+    pub game_won: bool, // Snake reached MAX_SNAKE_LENGTH
+    pub paused: bool,
+    pub countdown: u8, // Seconds left before the snake moves - the firmware counts it down
+    pub ticks_elapsed: u32, // Steps taken this game
+    elapsed_ms: u32,   // Time those steps took (each lasts one step_interval_ms)
+    score_history: Deque<u32, SCORE_HISTORY_SIZE>, // Oldest first
+    pub difficulty: Difficulty,
+    pub level: usize,      // Current level, starting at 1
+    start_level: usize,    // Level a new game begins on
+    next_level_score: u32, // Score that loads the next level
+
+    // Toroidal mode - the border walls act as portals to the other side
+    pub wrap_enabled: bool,
+
+    // The walls close in as food is eaten (never in the demo)
+    pub shrinking_enabled: bool,
+    inset: usize, // Rings the walls have closed in by so far this level
+
+    // A recorded demo is playing (set by the firmware, shown by the renderers)
+    pub demo_mode: bool,
+
+    // The firmware steers with ai_next_direction instead of the player
+    pub autopilot: bool,
+
+    // Ghost (practice) mode - the snake can run over itself, only walls end the game
+    pub allow_self_overlap: bool,
+
+    // Assist mode for beginners - heading into a wall stalls the snake until it's
+    // turned, instead of ending the game (snakes still crash into snakes)
+    pub assist: bool,
+
+    // Head-to-head with a second snake - takes effect at the next reset
+    // (the demo always plays alone)
+    pub two_player: bool,
+    crashed: [bool; 2], // Which snakes crashed on the step that ended the game
+
+    // Random numbers for food placement, and the seed they started from
+    rng: Rng,
+    seed: u32,
+
+    // How the game was before the last step, for undo (one-player games only)
+    prev_state: Option<undo::Snapshot>,
+
+    config: GameConfig, // How new games start
+}
+
+impl GameState {
+    pub fn new(seed: u32, difficulty: Difficulty) -> Self {
+        GameState::build(seed, difficulty, GameConfig::default())
+    }
+
+    // A game whose snakes start out as `config` says, instead of the usual 3 long heading right
+    // None if it asks for a board size other than BOARD_WIDTH x BOARD_HEIGHT
+    pub fn with_config(seed: u32, difficulty: Difficulty, config: GameConfig) -> Option<Self> {
+        if (config.board_width, config.board_height) != (BOARD_WIDTH, BOARD_HEIGHT) {
+            return None;
+        }
+        Some(GameState::build(seed, difficulty, config))
+    }
+
+    // new and with_config, once the config's been checked
+    fn build(seed: u32, difficulty: Difficulty, config: GameConfig) -> Self {
+        let mut game = GameState {
+            board: [[Cell::Empty; BOARD_WIDTH]; BOARD_HEIGHT],
+            snakes: [Snake::new(), Snake::new()],
+            food_positions: [Position { x: 0, y: 0 }; MAX_FOOD],
+            food_count: 0,
+            foods_eaten: 0,
+            combo: 1,
+            ticks_since_last_food: COMBO_WINDOW_STEPS,
+            bonus: None,
+            poison: None,
+            portals: None,
+            score: 0,
+            player2_score: 0,
+            high_score: 0,
+            game_over: false,
+            game_won: false,
+            paused: false,
+            countdown: COUNTDOWN_START,
+            ticks_elapsed: 0,
+            elapsed_ms: 0,
+            score_history: Deque::new(),
+            difficulty,
+            level: 1,
+            start_level: 1,
+            next_level_score: LEVEL_UP_POINTS,
+            wrap_enabled: false,
+            shrinking_enabled: false,
+            inset: 0,
+            demo_mode: false,
+            autopilot: false,
+            allow_self_overlap: false,
+            assist: false,
+            two_player: false,
+            crashed: [false; 2],
+            rng: Rng::new(seed),
+            seed,
+            prev_state: None,
+            config,
+        };
+
+        // Set up the first level's walls, snake and food
+        game.load_level(1);
+
+        game
+    }
+
+    fn update_board(&mut self) {
+        // Walls (border and layout), everything else starts out empty
+        for row in 0..BOARD_HEIGHT {
+            for col in 0..BOARD_WIDTH {
+                self.board[row][col] = if self.is_wall(Position { x: col, y: row }) {
+                    Cell::Wall
+                } else {
+                    Cell::Empty
+                };
+            }
+        }
+
+        // Portals before the snake, so a snake passing through stays visible
+        if let Some(portals) = self.portals {
+            for pos in portals {
+                self.board[pos.y][pos.x] = Cell::Portal;
+            }
+        }
+
+        // Place the snakes on board - each head goes last so it shows even when
+        // it's on top of a body (ghost mode)
+        let cells = [
+            (Cell::Snake, Cell::SnakeHead),
+            (Cell::Snake2, Cell::Snake2Head),
+        ];
+        for (snake, (body_cell, head_cell)) in self.snakes.iter().zip(cells) {
+            for pos in snake.body.iter() {
+                self.board[pos.y][pos.x] = body_cell;
+            }
+            if let Some(head) = snake.body.front() {
+                self.board[head.y][head.x] = head_cell;
+            }
+        }
+
+        // Place food on board
+        for i in 0..self.food_count {
+            let pos = self.food_positions[i];
+            self.board[pos.y][pos.x] = Cell::Food;
+        }
+
+        if let Some((pos, _)) = self.bonus {
+            self.board[pos.y][pos.x] = Cell::Bonus;
+        }
+
+        if let Some((pos, _)) = self.poison {
+            self.board[pos.y][pos.x] = Cell::Poison;
+        }
+    }
+
+    // Move the snake (both snakes in two-player mode) forward one step
+    pub fn move_snake(&mut self) -> StepEvent {
+        if self.game_over || self.game_won || self.paused || self.countdown > 0 {
+            return StepEvent::Nothing; // Don't move if game is over, paused or about to start
+        }
+
+        // Assist mode - a head about to go into a wall waits for a new direction instead
+        // Looked at before anything else, so a wait isn't counted as a step (time, combo, undo).
+        // Both snakes wait, so a two-player game stays in step, and the turns that were
+        // tried get used up so the next key has its go.
+        let players = self.players();
+        let into_wall = |player: usize| {
+            let direction = self.snakes[player].next_direction();
+            self.is_wall(self.next_head(player, direction))
+        };
+        if self.assist && (0..players).any(into_wall) {
+            for snake in &mut self.snakes[..players] {
+                snake.apply_queued_direction();
+            }
+            log_debug!("stalled at a wall");
+            return StepEvent::Nothing;
+        }
+
+        // So this step can be taken back
+        self.take_snapshot();
+
+        // The firmware runs one step per step_interval_ms, so that's how long this one lasts
+        self.ticks_elapsed += 1;
+        self.elapsed_ms += self.step_interval_ms();
+
+        // Every so often note the score for the graph, dropping the oldest once it's full
+        if self.ticks_elapsed.is_multiple_of(SCORE_SAMPLE_STEPS) {
+            if self.score_history.is_full() {
+                self.score_history.pop_front();
+            }
+            let _ = self.score_history.push_back(self.score);
+        }
+
+        // Too slow to the next food - the combo is over
+        self.ticks_since_last_food = self.ticks_since_last_food.saturating_add(1);
+        if self.ticks_since_last_food > COMBO_WINDOW_STEPS {
+            self.combo = 1;
+        }
+
+        // Take the next queued turn (if any) and work out where each head goes
+        let mut new_heads = [Position { x: 0, y: 0 }; 2];
+        let mut growing = [false; 2]; // Eating this step, so the tail stays put
+        for player in 0..players {
+            self.snakes[player].apply_queued_direction();
+            new_heads[player] = self.next_head(player, self.snakes[player].direction);
+            growing[player] = self.is_food(new_heads[player]);
+        }
+
+        // Check for collisions BEFORE moving
+        // Two heads landing on the same cell crash into each other
+        let mut crashed = [false; 2];
+        for player in 0..players {
+            crashed[player] = self.check_collision(new_heads[player], growing)
+                || (players == 2 && new_heads[0] == new_heads[1]);
+        }
+        if crashed.contains(&true) {
+            log_info!("crashed: {}, score {}", crashed, self.score);
+            self.crashed = crashed;
+            self.game_over = true;
+            self.high_score = self.high_score.max(self.score);
+            return StepEvent::Died;
+        }
+
+        // Claim the new head cells, so food placed below can't land under a snake
+        for &head in &new_heads[..players] {
+            self.board[head.y][head.x] = Cell::SnakeHead;
+        }
+
+        let mut event = StepEvent::Moved;
+        let mut shrink = [0; 2]; // Segments each snake loses to poison
+        let mut close_in = false; // Walls move in a ring once the snakes have moved
+
+        for player in 0..players {
+            let new_head = new_heads[player];
+
+            // Check if we're eating one of the food items
+            if let Some(food_index) = self.food().iter().position(|&food| food == new_head) {
+                // The combo multiplier is player one's
+                let multiplier = if player == 0 {
+                    // Eaten inside the window (combo wasn't reset above) - raise the multiplier
+                    if self.ticks_since_last_food <= COMBO_WINDOW_STEPS {
+                        self.combo = (self.combo + 1).min(MAX_COMBO);
+                    }
+                    self.ticks_since_last_food = 0;
+                    self.combo
+                } else {
+                    1
+                };
+                *self.score_mut(player) += FOOD_POINTS * multiplier;
+                event = StepEvent::Ate;
+                log_info!(
+                    "player {} ate food at {}, x{} combo",
+                    player,
+                    new_head,
+                    multiplier
+                );
+
+                if self.snakes[player].body.is_full() {
+                    // No room left to grow - the player has won
+                    log_info!("won with score {}", self.score);
+                    self.game_won = true;
+                    self.high_score = self.high_score.max(self.score);
+                    return StepEvent::Won;
+                }
+
+                // Move just the eaten food
+                self.place_new_food(food_index);
+
+                // Nowhere left to put food - nothing more to eat, so that's a win too
+                if self.food_count == 0 {
+                    log_info!("won with score {}", self.score);
+                    self.game_won = true;
+                    self.high_score = self.high_score.max(self.score);
+                    return StepEvent::Won;
+                }
+
+                // Every so often a bonus fruit shows up too
+                self.foods_eaten += 1;
+                if self.shrinking_enabled
+                    && !self.demo_mode
+                    && self.foods_eaten.is_multiple_of(SHRINK_EVERY_FOODS)
+                    && self.inset < self.max_inset()
+                {
+                    close_in = true;
+                }
+                if self.foods_eaten.is_multiple_of(BONUS_EVERY_FOODS) && self.bonus.is_none() {
+                    if let Some(pos) = self.random_free_cell() {
+                        self.bonus = Some((pos, BONUS_LIFETIME_STEPS));
+                    }
+                }
+
+                // And now and then some poison
+                if self.poison.is_none()
+                    && self.rng.next_range(100) < POISON_CHANCE_PERCENT as usize
+                {
+                    if let Some(pos) = self.random_free_cell() {
+                        self.poison = Some((pos, POISON_LIFETIME_STEPS));
+                    }
+                }
+            }
+
+            // Bonus fruit is worth extra points
+            if self.bonus.is_some_and(|(pos, _)| pos == new_head) {
+                *self.score_mut(player) += BONUS_POINTS;
+                log_info!("player {} ate the bonus at {}", player, new_head);
+                self.bonus = None;
+                event = StepEvent::Ate;
+            }
+
+            // Poison takes points (and segments) away
+            if self.poison.is_some_and(|(pos, _)| pos == new_head) {
+                let score = self.score_mut(player);
+                *score = score.saturating_sub(POISON_PENALTY);
+                log_info!("player {} ate poison at {}", player, new_head);
+                self.poison = None;
+                shrink[player] = POISON_SHRINK;
+                event = StepEvent::Poisoned;
+            }
+        }
+
+        // Bonus and poison that weren't eaten count down and disappear
+        self.bonus = self
+            .bonus
+            .and_then(|(pos, steps_left)| (steps_left > 1).then_some((pos, steps_left - 1)));
+        self.poison = self
+            .poison
+            .and_then(|(pos, steps_left)| (steps_left > 1).then_some((pos, steps_left - 1)));
+
+        let shortest = self.start_length(); // Poison can't take a snake below this
+        for player in 0..players {
+            let snake = &mut self.snakes[player];
+
+            // Move the snake: drop the tail (unless growing), then add the new head
+            if !growing[player] {
+                snake.body.pop_back();
+            }
+
+            // Can't fail - either the tail was just popped or is_full() was checked above
+            let _ = snake.body.push_front(new_heads[player]);
+
+            // Poisoned - lose segments off the tail, but keep at least the start length
+            for _ in 0..shrink[player] {
+                if snake.body.len() <= shortest {
+                    break;
+                }
+                snake.body.pop_back();
+            }
+        }
+
+        if close_in && self.close_in_walls() {
+            return StepEvent::Died;
+        }
+
+        // Update the board representation
+        self.update_board();
+
+        // Scored enough for the next level? (a two-player game stays on its layout)
+        if players == 1 && self.score >= self.next_level_score {
+            log_info!("level {} reached", self.level + 1);
+            self.load_level(self.level + 1);
+        }
+
+        event
+    }
+
+    // Shrinking mode - move the walls in a ring, clearing whatever is now inside them
+    // Returns true if the new ring came down on a snake, which ends the game
+    fn close_in_walls(&mut self) -> bool {
+        self.inset += 1;
+        log_info!("walls closed in to ring {}", self.inset);
+
+        let caught = [0, 1].map(|player| {
+            self.snakes[player]
+                .body
+                .iter()
+                .any(|&segment| self.is_wall(segment))
+        });
+        if caught.contains(&true) {
+            self.crashed = caught;
+            self.game_over = true;
+            self.high_score = self.high_score.max(self.score);
+            self.update_board();
+            return true;
+        }
+
+        // Food under the new walls moves inside, bonus and poison just go
+        for i in (0..self.food_count).rev() {
+            if self.is_wall(self.food_positions[i]) {
+                self.place_new_food(i);
+            }
+        }
+        if self.bonus.is_some_and(|(pos, _)| self.is_wall(pos)) {
+            self.bonus = None;
+        }
+        if self.poison.is_some_and(|(pos, _)| self.is_wall(pos)) {
+            self.poison = None;
+        }
+
+        // A portal needs a clear cell each side of it, so check around them too
+        if let Some(portals) = self.portals {
+            let blocked = portals.iter().any(|&pos| {
+                pos.x <= self.inset + 1
+                    || pos.x >= BOARD_WIDTH - 2 - self.inset
+                    || pos.y <= self.inset + 1
+                    || pos.y >= BOARD_HEIGHT - 2 - self.inset
+            });
+            if blocked {
+                self.place_portals();
+            }
+        }
+
+        false
+    }
+
+    // Score of player 0 (one) or 1 (two)
+    fn score_mut(&mut self, player: usize) -> &mut u32 {
+        if player == 0 {
+            &mut self.score
+        } else {
+            &mut self.player2_score
+        }
+    }
+
+    // Where `player`'s head ends up after one step in `direction`
+    // Stepping into a portal comes out one step past the other portal instead
+    fn next_head(&self, player: usize, direction: Direction) -> Position {
+        let new_head = self.step_from(self.snakes[player].head(), direction);
+
+        match self.portals {
+            Some([a, b]) if new_head == a => self.step_from(b, direction),
+            Some([a, b]) if new_head == b => self.step_from(a, direction),
+            _ => new_head,
+        }
+    }
+
+    // One step from `pos` in `direction`, going through the border in wrap mode
+    fn step_from(&self, pos: Position, direction: Direction) -> Position {
+        // `pos` is always inside the walls, so stepping by one can't underflow
+        let mut new_head = match direction {
+            Direction::Up => Position {
+                x: pos.x,
+                y: pos.y - 1,
+            },
+            Direction::Down => Position {
+                x: pos.x,
+                y: pos.y + 1,
+            },
+            Direction::Left => Position {
+                x: pos.x - 1,
+                y: pos.y,
+            },
+            Direction::Right => Position {
+                x: pos.x + 1,
+                y: pos.y,
+            },
+        };
+
+        if self.wrap_enabled {
+            // Stepping onto a border wall comes out the opposite side
+            if new_head.x == 0 {
+                new_head.x = BOARD_WIDTH - 2;
+            } else if new_head.x == BOARD_WIDTH - 1 {
+                new_head.x = 1;
+            }
+
+            if new_head.y == 0 {
+                new_head.y = BOARD_HEIGHT - 2;
+            } else if new_head.y == BOARD_HEIGHT - 1 {
+                new_head.y = 1;
+            }
+        }
+
+        new_head
+    }
+
+    // Autopilot - pick the move that gets closest to some food without crashing
+    // Only looks one step ahead, so it can still trap itself
+    // Distances ignore wrap-around, so in wrap mode it takes the long way sometimes
+    // Steers player one
+    pub fn ai_next_direction(&self) -> Direction {
+        let current = self.snakes[0].direction;
+        let (turn_a, turn_b) = match current {
+            Direction::Up | Direction::Down => (Direction::Left, Direction::Right),
+            Direction::Left | Direction::Right => (Direction::Up, Direction::Down),
+        };
+
+        // Manhattan distance to the nearest food (bonus fruit included)
+        let distance_to_food = |pos: Position| {
+            let bonus = self.bonus.map(|(bonus_pos, _)| bonus_pos);
+            self.food()
+                .iter()
+                .chain(bonus.iter())
+                .map(|food| pos.x.abs_diff(food.x) + pos.y.abs_diff(food.y))
+                .min()
+                .unwrap_or(0)
+        };
+
+        // Going straight comes first, so it wins ties
+        let mut best: Option<(Direction, usize)> = None;
+        for direction in [current, turn_a, turn_b] {
+            let pos = self.next_head(0, direction);
+            if self.check_collision(pos, [self.is_food(pos), false]) {
+                continue;
+            }
+
+            let distance = distance_to_food(pos);
+            if best.is_none_or(|(_, best_distance)| distance < best_distance) {
+                best = Some((direction, distance));
+            }
+        }
+
+        // Boxed in - keep going, nothing helps
+        best.map_or(current, |(direction, _)| direction)
+    }
+
+    // Is one of the (regular) food items here? Eating one makes the snake grow
+    fn is_food(&self, pos: Position) -> bool {
+        self.food().contains(&pos)
+    }
+
+    // Check if a position would cause a collision
+    // `growing` - which snakes eat on this step, so their tails stay where they are
+    // The autopilot looks ahead with this too, so not every logged hit is a crash
+    fn check_collision(&self, pos: Position, growing: [bool; 2]) -> bool {
+        // Check bounds (walls) - in wrap mode the border is a portal, not a wall
+        if !self.wrap_enabled
+            && (pos.x == 0 || pos.x >= BOARD_WIDTH - 1 || pos.y == 0 || pos.y >= BOARD_HEIGHT - 1)
+        {
+            log_debug!("collision with the border at {}", pos);
+            return true;
+        }
+
+        // Check interior walls from the layout
+        if self.board[pos.y][pos.x] == Cell::Wall {
+            log_debug!("collision with a wall at {}", pos);
+            return true;
+        }
+
+        // Check for hitting a snake body (either snake's), unless that's allowed
+        // A tail moves out of the way this step unless that snake is growing,
+        // so following right behind one is fine
+        if !self.allow_self_overlap {
+            for (snake, &growing) in self.snakes.iter().zip(&growing) {
+                let checked = if growing {
+                    snake.body.len()
+                } else {
+                    snake.body.len().saturating_sub(1)
+                };
+                if snake.body.iter().take(checked).any(|&seg| seg == pos) {
+                    log_debug!("collision with a snake at {}", pos);
+                    return true;
+                }
+            }
+        }
+
+        false
+    }
+
+    // Is there a wall here? Border (moved in by `inset` in shrinking mode) or current layout,
+    // worked out from the layout itself so it doesn't depend on the board having been redrawn
+    fn is_wall(&self, pos: Position) -> bool {
+        let inset = self.inset;
+        if pos.x <= inset
+            || pos.x >= BOARD_WIDTH - 1 - inset
+            || pos.y <= inset
+            || pos.y >= BOARD_HEIGHT - 1 - inset
+        {
+            return true;
+        }
+
+        LAYOUTS[(self.level - 1) % NUM_LAYOUTS][pos.y].as_bytes()[pos.x] == b'#'
+    }
+
+    // Check if food could go here (empty board cell, not a wall, the snake or other food)
+    fn is_free_cell(&self, pos: Position) -> bool {
+        if self.board[pos.y][pos.x] != Cell::Empty || self.is_wall(pos) {
+            return false;
+        }
+
+        // Double check against the snakes themselves in case the board is stale
+        if self
+            .snakes
+            .iter()
+            .any(|snake| snake.body.iter().any(|&seg| seg == pos))
+        {
+            return false;
+        }
+
+        // Same for food placed since the last board update
+        for i in 0..self.food_count {
+            if pos == self.food_positions[i] {
+                return false;
+            }
+        }
+
+        if let Some((bonus_pos, _)) = self.bonus {
+            if pos == bonus_pos {
+                return false;
+            }
+        }
+
+        if let Some((poison_pos, _)) = self.poison {
+            if pos == poison_pos {
+                return false;
+            }
+        }
+
+        if let Some(portals) = self.portals {
+            if portals.contains(&pos) {
+                return false;
+            }
+        }
+
+        true
+    }
+
+    // Find a random empty cell for a portal, away from the lines the snakes start out along
+    // All four neighbours must be open too, so coming out of it never lands in a wall
+    fn random_portal_cell(&mut self) -> Option<Position> {
+        let start = self.snakes[0].head();
+        let horizontal = self.config.start_direction.is_horizontal();
+        let along_start = move |pos: Position| {
+            let on_line = if horizontal {
+                pos.y == start.y
+            } else {
+                pos.x == start.x
+            };
+            on_line || pos.y == START_HEAD_2.y
+        };
+
+        for _ in 0..MAX_FOOD_ATTEMPTS {
+            let pos = self.random_free_cell()?;
+            let open_around = [
+                Position {
+                    x: pos.x,
+                    y: pos.y - 1,
+                },
+                Position {
+                    x: pos.x,
+                    y: pos.y + 1,
+                },
+                Position {
+                    x: pos.x - 1,
+                    y: pos.y,
+                },
+                Position {
+                    x: pos.x + 1,
+                    y: pos.y,
+                },
+            ]
+            .iter()
+            .all(|&next| !self.is_wall(next));
+
+            if open_around && !along_start(pos) {
+                return Some(pos);
+            }
+        }
+
+        None
+    }
+
+    // Put a new portal pair on the board (none if there's no good spot for both)
+    fn place_portals(&mut self) {
+        self.portals = None;
+
+        if let Some(a) = self.random_portal_cell() {
+            // Mark the first one so the second can't land on it
+            self.portals = Some([a, a]);
+            self.portals = self.random_portal_cell().map(|b| [a, b]);
+        }
+    }
+
+    // Find a random empty cell (None if the board is full)
+    fn random_free_cell(&mut self) -> Option<Position> {
+        // Pick random interior cells until we find an empty one
+        for _ in 0..MAX_FOOD_ATTEMPTS {
+            let candidate = Position {
+                x: self.rng.next_range(BOARD_WIDTH - 2) + 1,
+                y: self.rng.next_range(BOARD_HEIGHT - 2) + 1,
+            };
+
+            if self.is_free_cell(candidate) {
+                return Some(candidate);
+            }
+        }
+
+        // Board is getting full - scan every interior cell so we never give up
+        // while there's still an empty one
+        for row in 1..BOARD_HEIGHT - 1 {
+            for col in 1..BOARD_WIDTH - 1 {
+                let pos = Position { x: col, y: row };
+                if self.is_free_cell(pos) {
+                    return Some(pos);
+                }
+            }
+        }
+
+        None
+    }
+
+    // Move food item `index` to a new random empty cell
+    // If there's no room left the item is removed instead
+    fn place_new_food(&mut self, index: usize) {
+        match self.random_free_cell() {
+            Some(pos) => {
+                log_debug!("food {} placed at {}", index, pos);
+                self.food_positions[index] = pos;
+            }
+            None => {
+                log_debug!("no room for food {}", index);
+                self.food_count -= 1;
+                self.food_positions[index] = self.food_positions[self.food_count];
+            }
+        }
+    }
+
+    // Clear the food and scatter a fresh set of MAX_FOOD items
+    fn place_all_food(&mut self) {
+        self.food_count = 0;
+        self.update_board();
+
+        while self.food_count < MAX_FOOD {
+            match self.random_free_cell() {
+                Some(pos) => {
+                    self.food_positions[self.food_count] = pos;
+                    self.food_count += 1;
+                }
+                None => break,
+            }
+        }
+
+        self.update_board();
+    }
+
+    // Queue a turn for player one's snake
+    pub fn enqueue_direction(&mut self, direction: Direction) {
+        self.enqueue_player_direction(0, direction);
+    }
+
+    // Queue a turn for player 0 (one) or 1 (two), for a later step
+    pub fn enqueue_player_direction(&mut self, player: usize, direction: Direction) {
+        // Ignore turns while paused so the snake resumes the way it was going,
+        // and during the countdown so stray keys don't turn it on the first step
+        if self.paused || self.countdown > 0 || player >= self.players() {
+            return;
+        }
+
+        self.snakes[player].enqueue_direction(direction);
+    }
+
+    // Time between steps for the current difficulty and food eaten
+    pub fn step_interval_ms(&self) -> u32 {
+        let speedup = self
+            .foods_eaten
+            .saturating_mul(self.difficulty.step_ms_per_food());
+
+        self.difficulty
+            .start_step_ms()
+            .saturating_sub(speedup)
+            .max(MIN_STEP_MS)
+    }
+
+    // Current snake length (player one's)
+    pub fn snake_length(&self) -> usize {
+        self.snakes[0].body.len()
+    }
+
+    // Snakes in play - 2 for a two-player game
+    pub fn players(&self) -> usize {
+        if self.snakes[1].body.is_empty() {
+            1
+        } else {
+            2
+        }
+    }
+
+    // Who won a two-player game: the player (0 or 1) that didn't crash
+    // None while it's still going, or if both crashed on the same step
+    pub fn winner(&self) -> Option<usize> {
+        match self.crashed {
+            [false, true] => Some(0),
+            [true, false] => Some(1),
+            _ => None,
+        }
+    }
+
+    // How long this game has been running, in seconds (not counting pauses or the countdown)
+    pub fn elapsed_secs(&self) -> u32 {
+        self.elapsed_ms / 1_000
+    }
+
+    // Player one's score as it went along, oldest first (see SCORE_HISTORY_SIZE)
+    pub fn score_history(&self) -> impl ExactSizeIterator<Item = u32> + '_ {
+        self.score_history.iter().copied()
+    }
+
+    // Player one's snake segments, head first
+    pub fn snake_segments(&self) -> impl ExactSizeIterator<Item = &Position> {
+        self.snakes[0].body.iter()
+    }
+
+    // The bonus fruit is about to disappear (renderers flash it)
+    pub fn bonus_expiring(&self) -> bool {
+        matches!(self.bonus, Some((_, steps_left)) if steps_left <= BONUS_WARNING_STEPS)
+    }
+
+    // Food on the board right now (not counting the bonus fruit)
+    pub fn food(&self) -> &[Position] {
+        &self.food_positions[..self.food_count]
+    }
+
+    // Direction player one's snake moved on its last step
+    pub fn direction(&self) -> Direction {
+        self.player_direction(0)
+    }
+
+    // Same for player 0 (one) or 1 (two)
+    pub fn player_direction(&self, player: usize) -> Direction {
+        self.snakes[player].direction
+    }
+
+    // Debugging aid - does the board agree with the snakes and the food?
+    // Checks every snake cell on the board belongs to that snake and every segment
+    // shows as a snake, each snake is inside the border with its head drawn as one,
+    // and the food cells are exactly the food items. Holds after every step except
+    // the one that wins (that returns before the board is redrawn).
+    pub fn invariants_hold(&self) -> bool {
+        let inside = |pos: &Position| {
+            (1..BOARD_WIDTH - 1).contains(&pos.x) && (1..BOARD_HEIGHT - 1).contains(&pos.y)
+        };
+        let on_snake =
+            |player: usize, pos: Position| self.snakes[player].body.iter().any(|&seg| seg == pos);
+
+        for (player, snake) in self.snakes[..self.players()].iter().enumerate() {
+            if snake.body.is_empty() || !snake.body.iter().all(inside) {
+                return false;
+            }
+
+            // Every segment shows as a snake (the other one may be drawn over it in ghost mode)
+            let all_drawn = snake.body.iter().all(|seg| {
+                matches!(
+                    self.board[seg.y][seg.x],
+                    Cell::Snake | Cell::SnakeHead | Cell::Snake2 | Cell::Snake2Head
+                )
+            });
+            if !all_drawn {
+                return false;
+            }
+
+            // Player two is drawn last, so only their head is sure to be on top
+            let head = snake.head();
+            if player == 1 && self.board[head.y][head.x] != Cell::Snake2Head {
+                return false;
+            }
+        }
+
+        let mut food_cells = 0;
+        for row in 0..BOARD_HEIGHT {
+            for col in 0..BOARD_WIDTH {
+                let pos = Position { x: col, y: row };
+                let belongs = match self.board[row][col] {
+                    Cell::Snake | Cell::SnakeHead => on_snake(0, pos),
+                    Cell::Snake2 | Cell::Snake2Head => on_snake(1, pos),
+                    Cell::Food => {
+                        food_cells += 1;
+                        self.food().contains(&pos)
+                    }
+                    _ => true,
+                };
+                if !belongs {
+                    return false;
+                }
+            }
+        }
+
+        food_cells == self.food_count
+    }
+
+    // Pause or resume (nothing to pause once the game has ended)
+    pub fn toggle_pause(&mut self) {
+        if !self.game_over && !self.game_won {
+            self.paused = !self.paused;
+        }
+    }
+
+    // Turn wrap-around mode on or off
+    pub fn toggle_wrap(&mut self) {
+        self.wrap_enabled = !self.wrap_enabled;
+    }
+
+    // Start new games on the next layout (wraps back to level 1)
+    pub fn next_start_level(&mut self) {
+        self.set_start_level(self.start_level % NUM_LAYOUTS + 1);
+    }
+
+    // Start new games on level `level` (1 to NUM_LAYOUTS), beginning one now
+    // It keeps the seed, so a seed typed into the menu still replays on any level
+    pub fn set_start_level(&mut self, level: usize) {
+        self.start_level = level.clamp(1, NUM_LAYOUTS);
+        self.reset_with_seed(self.seed);
+    }
+
+    // Switch to level `level`, keeping the score
+    // The snake starts over at a spot that's clear in the new layout
+    pub fn load_level(&mut self, level: usize) {
+        self.level = level.max(1);
+        self.next_level_score = self.score + LEVEL_UP_POINTS;
+        self.inset = 0; // Each level starts at full size
+
+        // Board with only the new layout's walls, so the start can be checked
+        for snake in self.snakes.iter_mut() {
+            snake.body.clear();
+        }
+        self.food_count = 0;
+        self.bonus = None;
+        self.poison = None;
+        self.portals = None;
+        // Player two goes on their own row, heading the other way (never in the demo)
+        // First, so player one's start can be checked against them
+        let length = self.start_length();
+        if self.two_player && !self.demo_mode {
+            let head = Position {
+                x: START_HEAD_2.x.min(BOARD_WIDTH - 1 - length),
+                y: START_HEAD_2.y,
+            };
+            self.snakes[1].lay_out(head, Direction::Left, length);
+        }
+        self.update_board();
+
+        self.place_snake_at_start();
+
+        // Portals go down before the food so nothing gets placed on them
+        self.place_portals();
+
+        // Scatter new food over the fresh board
+        self.place_all_food();
+    }
+
+    // Lay the snake out as the config says, centred on START_HEAD if the layout allows it
+    // Otherwise use the first spot with clear cells for the body and in front of it
+    fn place_snake_at_start(&mut self) {
+        let direction = self.config.start_direction;
+        let length = self.start_length();
+
+        // The middle of the body plus the clear run in front goes in the middle
+        let shift = (length as isize - 1 - START_CLEARANCE as isize) / 2;
+        let mut head = direction.offset(START_HEAD, shift).unwrap_or(START_HEAD);
+
+        if !self.start_is_clear(head, direction, length) {
+            'search: for y in 1..BOARD_HEIGHT - 1 {
+                for x in 1..BOARD_WIDTH - 1 {
+                    if self.start_is_clear(Position { x, y }, direction, length) {
+                        head = Position { x, y };
+                        break 'search;
+                    }
+                }
+            }
+        }
+
+        self.snakes[0].lay_out(head, direction, length);
+    }
+
+    // The config's initial length, cut down to what fits across the inside of the
+    // board with START_CLEARANCE to spare (and to MAX_SNAKE_LENGTH)
+    // The demo was recorded with the usual START_LENGTH, so it always uses that
+    fn start_length(&self) -> usize {
+        if self.demo_mode {
+            return START_LENGTH;
+        }
+        let inside = if self.config.start_direction.is_horizontal() {
+            self.config.board_width - 2
+        } else {
+            self.config.board_height - 2
+        };
+        let longest = (inside - START_CLEARANCE).min(MAX_SNAKE_LENGTH);
+        self.config.initial_length.clamp(1, longest)
+    }
+
+    // How many rings the walls can close in by, leaving the inside start_length() high
+    // (none at all for a snake that starts longer than the board is high)
+    fn max_inset(&self) -> usize {
+        (self.config.board_height - 2).saturating_sub(self.start_length()) / 2
+    }
+
+    // Check the board is empty from the tail to START_CLEARANCE cells past the head
+    fn start_is_clear(&self, head: Position, direction: Direction, length: usize) -> bool {
+        let first = 1 - length as isize; // The tail
+        (first..=START_CLEARANCE as isize).all(|steps| {
+            direction
+                .offset(head, steps)
+                .is_some_and(|pos| self.board[pos.y][pos.x] == Cell::Empty)
+        })
+    }
+
+    // NEW: Reset the game
+    // The new game's food comes from a fresh seed, drawn from the old game's numbers,
+    // so seed() is always the one that replays the game being played
+    pub fn reset(&mut self) {
+        let seed = self.rng.next_u32(); // Never 0 - xorshift can't produce it
+        self.reset_with_seed(seed);
+    }
+
+    // Reset with the food placement starting over from `seed`, so a game can be replayed exactly
+    pub fn reset_with_seed(&mut self, seed: u32) {
+        self.rng = Rng::new(seed);
+        self.seed = seed;
+
+        self.score = 0;
+        self.player2_score = 0;
+        self.crashed = [false; 2];
+        self.game_over = false;
+        self.game_won = false;
+        self.paused = false;
+        self.countdown = COUNTDOWN_START;
+        self.ticks_elapsed = 0;
+        self.elapsed_ms = 0;
+        self.score_history.clear();
+        self.foods_eaten = 0;
+        self.combo = 1;
+        self.ticks_since_last_food = COMBO_WINDOW_STEPS;
+        self.prev_state = None; // Nothing to undo in a new game
+
+        // Back to the first level with a fresh snake and food
+        self.load_level(self.start_level);
+    }
+
+    // The seed the current game's food sequence started from
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+// Run on the computer, not the board:
+// cargo test -p snake-core --target $(rustc -vV | sed -n 's/host: //p')
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use std::vec::Vec;
+
+    // A level 1 game that's already moving - no countdown, and no portals, bonus or poison
+    fn game() -> GameState {
+        let mut game = GameState::new(1, Difficulty::Slow);
+        game.countdown = 0;
+        game.portals = None;
+        game.bonus = None;
+        game.poison = None;
+        game.update_board();
+        game
+    }
+
+    // Put player one's snake at `body` (head first) heading `direction`, with food only at `food`
+    fn set_up(
+        game: &mut GameState,
+        body: &[(usize, usize)],
+        direction: Direction,
+        food: &[(usize, usize)],
+    ) {
+        let snake = &mut game.snakes[0];
+        snake.body.clear();
+        for &(x, y) in body {
+            snake.body.push_back(Position { x, y }).unwrap();
+        }
+        snake.direction = direction;
+        snake.queue_len = 0;
+
+        for (i, &(x, y)) in food.iter().enumerate() {
+            game.food_positions[i] = Position { x, y };
+        }
+        game.food_count = food.len();
+        game.update_board();
+    }
+
+    // Player one's segments as (x, y), head first
+    fn body(game: &GameState) -> Vec<(usize, usize)> {
+        game.snake_segments().map(|pos| (pos.x, pos.y)).collect()
+    }
+
+    #[test]
+    fn moving_into_a_wall_sets_game_over() {
+        let mut game = game();
+        set_up(
+            &mut game,
+            &[(18, 7), (17, 7), (16, 7)],
+            Direction::Right,
+            &[(5, 5)],
+        );
+
+        assert_eq!(game.move_snake(), StepEvent::Died);
+        assert!(game.game_over);
+    }
+
+    #[test]
+    fn eating_food_scores_10() {
+        let mut game = game();
+        set_up(
+            &mut game,
+            &[(10, 7), (9, 7), (8, 7)],
+            Direction::Right,
+            &[(11, 7)],
+        );
+
+        assert_eq!(game.move_snake(), StepEvent::Ate);
+        assert_eq!(game.score, 10);
+        assert_eq!(body(&game), [(11, 7), (10, 7), (9, 7), (8, 7)]);
+    }
+
+    // Cover every empty cell inside the border, apart from `free`
+    fn fill_board_except(game: &mut GameState, free: &[(usize, usize)]) {
+        for row in 1..BOARD_HEIGHT - 1 {
+            for col in 1..BOARD_WIDTH - 1 {
+                if game.board[row][col] == Cell::Empty && !free.contains(&(col, row)) {
+                    game.board[row][col] = Cell::Snake;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn food_lands_on_an_empty_cell_when_the_board_is_nearly_full() {
+        let free = [(1, 1), (7, 9), (18, 13)];
+
+        // Plenty of seeds, so both the random tries and the scan get used
+        for seed in 1..200 {
+            let mut game = game();
+            game.rng = Rng::new(seed);
+            set_up(
+                &mut game,
+                &[(10, 7), (9, 7), (8, 7)],
+                Direction::Right,
+                &[(5, 5)],
+            );
+            fill_board_except(&mut game, &free);
+
+            game.place_new_food(0);
+
+            assert_eq!(game.food_count, 1);
+            let food = game.food()[0];
+            assert!(free.contains(&(food.x, food.y)), "food at {:?}", food);
+        }
+    }
+
+    #[test]
+    fn food_is_removed_when_the_board_is_full() {
+        let mut game = game();
+        set_up(
+            &mut game,
+            &[(10, 7), (9, 7), (8, 7)],
+            Direction::Right,
+            &[(5, 5)],
+        );
+        fill_board_except(&mut game, &[]);
+
+        game.place_new_food(0);
+
+        assert_eq!(game.food_count, 0);
+    }
+
+    #[test]
+    fn reversing_is_ignored() {
+        let mut game = game();
+        set_up(
+            &mut game,
+            &[(10, 7), (9, 7), (8, 7)],
+            Direction::Right,
+            &[(5, 5)],
+        );
+
+        game.enqueue_direction(Direction::Left);
+        assert_eq!(game.move_snake(), StepEvent::Moved);
+
+        assert_eq!(game.direction(), Direction::Right);
+        assert_eq!(body(&game), [(11, 7), (10, 7), (9, 7)]);
+    }
+
+    #[test]
+    fn reversing_cant_sneak_in_behind_a_quick_turn() {
+        let mut game = game();
+        set_up(
+            &mut game,
+            &[(10, 7), (9, 7), (8, 7)],
+            Direction::Right,
+            &[(5, 5)],
+        );
+
+        // Down then up, both before the next step - up would reverse the down turn
+        game.enqueue_direction(Direction::Down);
+        game.enqueue_direction(Direction::Up);
+        game.move_snake();
+        game.move_snake();
+
+        assert!(!game.game_over);
+        assert_eq!(game.direction(), Direction::Down);
+        assert_eq!(body(&game), [(10, 9), (10, 8), (10, 7)]);
+    }
+
+    #[test]
+    fn eating_one_food_leaves_the_others() {
+        let mut game = game();
+        set_up(
+            &mut game,
+            &[(10, 7), (9, 7), (8, 7)],
+            Direction::Right,
+            &[(3, 3), (11, 7), (15, 12)],
+        );
+
+        assert_eq!(game.move_snake(), StepEvent::Ate);
+
+        // The eaten one moved, the other two are where they were
+        assert_eq!(game.food_count, 3);
+        assert_eq!(game.food()[0], Position { x: 3, y: 3 });
+        assert_ne!(game.food()[1], Position { x: 11, y: 7 });
+        assert_eq!(game.food()[2], Position { x: 15, y: 12 });
+        for food in game.food() {
+            assert_eq!(game.board[food.y][food.x], Cell::Food);
+        }
+    }
+
+    #[test]
+    fn food_never_lands_on_a_wall() {
+        for level in 1..=NUM_LAYOUTS {
+            for seed in 1..20 {
+                let mut game = game();
+                game.rng = Rng::new(seed);
+                game.load_level(level);
+                // Leave a scattering of empty cells, some right next to walls
+                let free = [(1, 1), (2, 3), (4, 4), (9, 6), (16, 10), (18, 13)];
+                fill_board_except(&mut game, &free);
+                let empty_before = game.board;
+
+                for _ in 0..10 {
+                    game.place_new_food(0);
+                    let food = game.food()[0];
+                    assert!(
+                        !game.is_wall(food),
+                        "level {} food in a wall at {:?}",
+                        level,
+                        food
+                    );
+                    assert_eq!(empty_before[food.y][food.x], Cell::Empty);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_step_moves_every_segment_along() {
+        let mut game = game();
+        // Bent snake, so each segment has to take the place of the one in front
+        set_up(
+            &mut game,
+            &[(10, 7), (10, 8), (9, 8), (8, 8)],
+            Direction::Up,
+            &[(5, 5)],
+        );
+
+        assert_eq!(game.move_snake(), StepEvent::Moved);
+        assert_eq!(body(&game), [(10, 6), (10, 7), (10, 8), (9, 8)]);
+
+        game.enqueue_direction(Direction::Left);
+        game.move_snake();
+        assert_eq!(body(&game), [(9, 6), (10, 6), (10, 7), (10, 8)]);
+        assert_eq!(game.board[8][9], Cell::Empty); // Old tail cleared off the board
+    }
+
+    #[test]
+    fn eating_grows_without_dropping_the_tail() {
+        let mut game = game();
+        set_up(
+            &mut game,
+            &[(10, 7), (9, 7), (8, 7)],
+            Direction::Right,
+            &[(11, 7)],
+        );
+
+        game.move_snake();
+
+        assert_eq!(game.score, 10);
+        assert_eq!(game.snake_length(), 4);
+        assert_eq!(body(&game).last(), Some(&(8, 7)));
+
+        // The next step moves normally again
+        game.move_snake();
+        assert_eq!(body(&game), [(12, 7), (11, 7), (10, 7), (9, 7)]);
+    }
+
+    #[test]
+    fn hitting_an_inside_wall_sets_game_over() {
+        let mut game = game();
+        game.load_level(2); // Bars along rows 3 and 11, from x 4 to 15
+        game.portals = None;
+        set_up(
+            &mut game,
+            &[(10, 4), (10, 5), (10, 6)],
+            Direction::Up,
+            &[(2, 2)],
+        );
+
+        assert_eq!(game.move_snake(), StepEvent::Died);
+        assert!(game.game_over);
+        assert_eq!(body(&game), [(10, 4), (10, 5), (10, 6)]); // Crashed without moving
+    }
+
+    #[test]
+    fn running_into_itself_ends_the_game() {
+        let mut game = game();
+        // Heading left along row 5, with the body curling round underneath
+        set_up(
+            &mut game,
+            &[(5, 5), (6, 5), (6, 6), (5, 6), (4, 6)],
+            Direction::Left,
+            &[(2, 2)],
+        );
+
+        game.enqueue_direction(Direction::Down);
+
+        assert_eq!(game.move_snake(), StepEvent::Died);
+        assert!(game.game_over);
+    }
+
+    #[test]
+    fn change_direction_rejects_reversing() {
+        let mut snake = Snake::new();
+        snake.lay_out(Position { x: 10, y: 7 }, Direction::Right, START_LENGTH);
+
+        assert!(!snake.change_direction(Direction::Left)); // Straight back
+        assert!(!snake.change_direction(Direction::Right)); // Already going that way
+        assert_eq!(snake.direction, Direction::Right);
+
+        assert!(snake.change_direction(Direction::Up));
+        assert!(!snake.change_direction(Direction::Down));
+        assert_eq!(snake.direction, Direction::Up);
+    }
+
+    #[test]
+    fn following_your_own_tail_is_fine() {
+        let mut game = game();
+        // A square of four - each step the head goes where the tail just was
+        set_up(
+            &mut game,
+            &[(5, 5), (6, 5), (6, 6), (5, 6)],
+            Direction::Left,
+            &[(12, 12)],
+        );
+
+        game.enqueue_direction(Direction::Down);
+        assert_eq!(game.move_snake(), StepEvent::Moved);
+        assert_eq!(body(&game), [(5, 6), (5, 5), (6, 5), (6, 6)]);
+    }
+
+    #[test]
+    fn the_tail_only_counts_when_the_snake_is_growing() {
+        let mut game = game();
+        set_up(
+            &mut game,
+            &[(5, 5), (6, 5), (6, 6), (5, 6)],
+            Direction::Left,
+            &[(12, 12)],
+        );
+        let tail = Position { x: 5, y: 6 };
+
+        // Moving on - the tail gets out of the way
+        assert!(!game.check_collision(tail, [false, false]));
+        // Growing - the tail stays where it is, so it's in the way
+        assert!(game.check_collision(tail, [true, false]));
+    }
+
+    #[test]
+    fn the_seed_after_a_restart_replays_that_game() {
+        let mut game = game();
+        game.reset_with_seed(1234);
+        game.reset(); // r on the game over screen
+        let seed = game.seed();
+        assert_ne!(seed, 1234);
+
+        // Typing the seed shown into the menu
+        let mut replay = GameState::new(99, Difficulty::Slow);
+        replay.reset_with_seed(seed);
+
+        assert_eq!(replay.food(), game.food());
+        assert_eq!(replay.bonus, game.bonus);
+        assert_eq!(replay.portals, game.portals);
+    }
+
+    // The menu's order: the seed, then the options (see apply_choice in the firmware)
+    fn menu_game(seed: u32, level: usize) -> GameState {
+        let mut game = GameState::new(99, Difficulty::Slow);
+        game.reset_with_seed(seed);
+        game.set_start_level(level);
+        game
+    }
+
+    #[test]
+    fn a_seed_typed_into_the_menu_replays() {
+        let game = menu_game(1234, 2);
+        assert_eq!(game.seed(), 1234);
+
+        // The seed on the game over screen, typed back in
+        let replay = menu_game(game.seed(), 2);
+        assert_eq!(replay.seed(), 1234);
+        assert_eq!(replay.food(), game.food());
+        assert_eq!(replay.portals, game.portals);
+
+        // A restart picks a new seed, and that one replays too
+        let mut game = game;
+        game.reset();
+        let replay = menu_game(game.seed(), 2);
+        assert_eq!(replay.food(), game.food());
+        assert_eq!(replay.portals, game.portals);
+    }
+
+    #[test]
+    fn segments_stay_in_order_after_many_steps() {
+        use std::collections::VecDeque;
+
+        let mut game = game();
+        game.next_level_score = u32::MAX; // A new level would lay the snake out again
+        set_up(
+            &mut game,
+            &[(4, 2), (3, 2), (2, 2)],
+            Direction::Right,
+            &[(9, 2)],
+        );
+        let mut expected: VecDeque<(usize, usize)> = body(&game).into();
+
+        // Laps of a rectangle - long enough for the ring buffer to wrap round many times,
+        // and for the snake to grow a good way as it eats
+        for step in 0..1_000 {
+            let (x, y) = expected[0];
+            let turn = match (x, y) {
+                (17, 2) => Some(Direction::Down),
+                (17, 12) => Some(Direction::Left),
+                (2, 12) => Some(Direction::Up),
+                (2, 2) => Some(Direction::Right),
+                _ => None,
+            };
+            if let Some(direction) = turn {
+                game.enqueue_direction(direction);
+            }
+            let direction = turn.unwrap_or(game.direction());
+            let next = direction.offset(Position { x, y }, 1).unwrap();
+
+            let eating = game.food().contains(&next);
+            game.move_snake();
+            assert!(!game.game_over, "crashed on step {}", step);
+
+            expected.push_front((next.x, next.y));
+            if !eating {
+                expected.pop_back();
+            }
+            assert_eq!(body(&game), Vec::from(expected.clone()), "step {}", step);
+
+            // Keep poison (which shrinks it) and bonus fruit out of the way
+            game.bonus = None;
+            game.poison = None;
+
+            // Now and then put food on the path (up to 40 long, well short of a 50 cell lap)
+            if step % 7 == 0 && game.snake_length() < 40 {
+                let ahead = game.direction().offset(next, 2).unwrap();
+                if game.board[ahead.y][ahead.x] == Cell::Empty && !game.is_wall(ahead) {
+                    game.food_positions[0] = ahead;
+                }
+            }
+            game.update_board();
+        }
+
+        assert!(game.snake_length() > 20);
+    }
+
+    #[test]
+    fn poison_shrinks_the_snake_down_to_the_minimum() {
+        let mut game = game();
+        game.score = 50;
+        set_up(
+            &mut game,
+            &[(10, 7), (9, 7), (8, 7), (7, 7), (6, 7), (5, 7)],
+            Direction::Right,
+            &[(2, 2)],
+        );
+        game.poison = Some((Position { x: 11, y: 7 }, POISON_LIFETIME_STEPS));
+        game.update_board();
+
+        assert_eq!(game.move_snake(), StepEvent::Poisoned);
+        assert_eq!(body(&game), [(11, 7), (10, 7), (9, 7), (8, 7)]);
+        assert_eq!(game.score, 50 - POISON_PENALTY);
+        assert_eq!(game.poison, None);
+        // The cells it lost are cleared off the board
+        for x in 5..=7 {
+            assert_eq!(game.board[7][x], Cell::Empty);
+        }
+
+        // Another poison would take it to 2, but it stops at the start length
+        game.poison = Some((Position { x: 12, y: 7 }, POISON_LIFETIME_STEPS));
+        game.update_board();
+        game.move_snake();
+        assert_eq!(body(&game), [(12, 7), (11, 7), (10, 7)]);
+        assert_eq!(game.board[7][9], Cell::Empty);
+        assert_eq!(game.board[7][12], Cell::SnakeHead);
+    }
+
+    // Let the autopilot steer for up to `steps` steps
+    // (its turns only depend on what's on the board, so they repeat along with the food)
+    fn autopilot(game: &mut GameState, steps: usize) {
+        game.countdown = 0;
+        for _ in 0..steps {
+            game.enqueue_direction(game.ai_next_direction());
+            game.move_snake();
+        }
+    }
+
+    // A game started from the menu with `seed`, after the autopilot has had `steps` steps
+    fn autopilot_game(seed: u32, steps: usize) -> GameState {
+        let mut game = menu_game(seed, 1);
+        autopilot(&mut game, steps);
+        game
+    }
+
+    #[test]
+    fn the_same_seed_and_inputs_play_the_same_game() {
+        for steps in [10, 100, 400] {
+            let first = autopilot_game(4242, steps);
+            let second = autopilot_game(4242, steps);
+
+            assert_eq!(body(&first), body(&second), "after {} steps", steps);
+            assert_eq!(first.score, second.score);
+            assert_eq!(first.food(), second.food());
+            assert_eq!(first.bonus, second.bonus);
+            assert_eq!(first.poison, second.poison);
+            assert_eq!(first.portals, second.portals);
+            assert_eq!(first.level, second.level);
+            assert_eq!(first.game_over, second.game_over);
+        }
+
+        // It's the seed that decides it - another one puts the food somewhere else
+        let first = autopilot_game(4242, 0);
+        let other = autopilot_game(4243, 0);
+        assert_ne!(first.food(), other.food());
+    }
+
+    #[test]
+    fn the_seed_shown_after_a_restart_replays_the_whole_game() {
+        let mut game = menu_game(4242, 3);
+        autopilot(&mut game, 50);
+        game.reset(); // r - a new game, with a new seed
+        autopilot(&mut game, 300);
+
+        // Typed into the menu, with the same level picked
+        let mut replay = menu_game(game.seed(), 3);
+        autopilot(&mut replay, 300);
+
+        assert_eq!(body(&replay), body(&game));
+        assert_eq!(replay.score, game.score);
+        assert_eq!(replay.food(), game.food());
+        assert_eq!(replay.bonus, game.bonus);
+        assert_eq!(replay.poison, game.poison);
+        assert_eq!(replay.portals, game.portals);
+        assert_eq!(replay.level, game.level);
+    }
+
+    #[test]
+    fn the_board_matches_the_snakes_after_random_steps() {
+        let directions = [
+            Direction::Up,
+            Direction::Down,
+            Direction::Left,
+            Direction::Right,
+        ];
+
+        for (seed, two_player) in [(1, false), (77, false), (9001, true)] {
+            // Not game() - portals, bonus fruit and poison are all wanted here
+            let mut game = GameState::new(seed, Difficulty::Slow);
+            game.two_player = two_player;
+            game.reset();
+            let mut keys = Rng::new(seed + 1);
+
+            for step in 0..5_000 {
+                game.countdown = 0;
+                // A new direction about one step in three, for each player
+                for player in 0..game.players() {
+                    if keys.next_range(3) == 0 {
+                        let direction = directions[keys.next_range(4)];
+                        game.enqueue_player_direction(player, direction);
+                    }
+                }
+
+                game.move_snake();
+                if !game.game_won {
+                    assert!(game.invariants_hold(), "seed {} step {}", seed, step);
+                }
+
+                if game.game_over || game.game_won {
+                    game.reset();
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn waiting_at_a_wall_in_assist_mode_isnt_a_step() {
+        let mut game = game();
+        game.assist = true;
+        set_up(
+            &mut game,
+            &[(17, 7), (16, 7), (15, 7)],
+            Direction::Right,
+            &[(16, 2)],
+        );
+        game.move_snake(); // Up to the wall, leaving something to undo
+        game.combo = 2;
+        game.ticks_since_last_food = 3;
+        let ticks = game.ticks_elapsed;
+        let elapsed_ms = game.elapsed_ms;
+
+        for _ in 0..3 {
+            assert_eq!(game.move_snake(), StepEvent::Nothing);
+        }
+        assert_eq!(body(&game), [(18, 7), (17, 7), (16, 7)]);
+        assert_eq!(game.ticks_elapsed, ticks);
+        assert_eq!(game.elapsed_ms, elapsed_ms);
+        assert_eq!((game.combo, game.ticks_since_last_food), (2, 3));
+
+        // Undo still goes back to before the last real step
+        let mut undone = game.clone();
+        assert!(undone.restore_previous());
+        assert_eq!(body(&undone), [(17, 7), (16, 7), (15, 7)]);
+
+        // A turn away from the wall gets it going again
+        game.enqueue_direction(Direction::Down);
+        assert_eq!(game.move_snake(), StepEvent::Moved);
+        assert_eq!(body(&game), [(18, 8), (18, 7), (17, 7)]);
+        assert_eq!(game.ticks_elapsed, ticks + 1);
+    }
+
+    #[test]
+    fn undo_puts_everything_back_where_it_was() {
+        let mut game = game();
+        set_up(
+            &mut game,
+            &[(10, 7), (10, 8), (9, 8), (8, 8)],
+            Direction::Up,
+            &[(10, 6), (3, 3)],
+        );
+        let board = game.board;
+
+        assert_eq!(game.move_snake(), StepEvent::Ate);
+        assert!(game.can_undo());
+        assert!(game.restore_previous());
+
+        assert_eq!(body(&game), [(10, 7), (10, 8), (9, 8), (8, 8)]);
+        assert_eq!(game.direction(), Direction::Up);
+        assert_eq!(game.score, 0);
+        assert_eq!(
+            game.food(),
+            [Position { x: 10, y: 6 }, Position { x: 3, y: 3 }]
+        );
+        assert_eq!(game.board, board);
+
+        // Only one step back
+        assert!(!game.can_undo());
+        assert!(!game.restore_previous());
+    }
+
+    #[test]
+    fn undo_takes_back_the_crash() {
+        let mut game = game();
+        set_up(
+            &mut game,
+            &[(18, 7), (17, 7), (16, 7)],
+            Direction::Right,
+            &[(5, 5)],
+        );
+        assert_eq!(game.move_snake(), StepEvent::Died);
+        assert!(game.restore_previous());
+
+        assert!(!game.game_over);
+        assert_eq!(game.countdown, COUNTDOWN_START); // A moment to pick another way
+        assert_eq!(body(&game), [(18, 7), (17, 7), (16, 7)]);
+        assert_eq!(game.board[7][18], Cell::SnakeHead);
+
+        // And it carries on from there
+        game.countdown = 0;
+        game.enqueue_direction(Direction::Up);
+        assert_eq!(game.move_snake(), StepEvent::Moved);
+        assert_eq!(body(&game), [(18, 6), (18, 7), (17, 7)]);
+    }
+
+    #[test]
+    fn a_longer_start_keeps_more_of_the_snake_and_the_board() {
+        let config = GameConfig {
+            initial_length: 5,
+            ..GameConfig::default()
+        };
+        let mut game = GameState::with_config(1, Difficulty::Slow, config).unwrap();
+        game.countdown = 0;
+        game.portals = None;
+        game.bonus = None;
+        set_up(
+            &mut game,
+            &[(10, 7), (9, 7), (8, 7), (7, 7), (6, 7), (5, 7)],
+            Direction::Right,
+            &[(2, 2)],
+        );
+        game.poison = Some((Position { x: 11, y: 7 }, POISON_LIFETIME_STEPS));
+        game.update_board();
+
+        assert_eq!(game.move_snake(), StepEvent::Poisoned);
+        assert_eq!(game.snake_length(), 5); // Two off would leave 4
+
+        // 13 rows inside, less 5 for the snake, leaves 8 - a ring takes one off the top and bottom
+        assert_eq!(game.max_inset(), 4);
+        assert_eq!(GameState::new(1, Difficulty::Slow).max_inset(), 5);
+    }
+
+    #[test]
+    fn a_length_6_start_is_laid_out_in_a_line() {
+        let config = GameConfig {
+            initial_length: 6,
+            ..GameConfig::default()
+        };
+        let mut game = GameState::with_config(1, Difficulty::Slow, config).unwrap();
+        game.reset();
+
+        // Heading right with the tail behind, moved along so the whole snake sits in the middle
+        assert_eq!(game.snake_length(), 6);
+        assert_eq!(
+            body(&game),
+            [(11, 7), (10, 7), (9, 7), (8, 7), (7, 7), (6, 7)]
+        );
+        assert_eq!(game.direction(), Direction::Right);
+        assert_eq!(game.board[7][11], Cell::SnakeHead);
+        for x in 6..=10 {
+            assert_eq!(game.board[7][x], Cell::Snake);
+        }
+        assert!(game.invariants_hold());
+
+        // Far too long for the board - cut down to what fits with room in front
+        let config = GameConfig {
+            initial_length: 500,
+            ..GameConfig::default()
+        };
+        let game = GameState::with_config(1, Difficulty::Slow, config).unwrap();
+        assert_eq!(game.snake_length(), BOARD_WIDTH - 2 - START_CLEARANCE);
+    }
+
+    #[test]
+    fn only_the_built_in_board_size_is_accepted() {
+        let config = GameConfig::default();
+        assert_eq!(
+            (config.board_width, config.board_height),
+            (BOARD_WIDTH, BOARD_HEIGHT)
+        );
+        assert!(GameState::with_config(1, Difficulty::Slow, config).is_some());
+
+        for (board_width, board_height) in [(30, 15), (20, 10), (0, 0)] {
+            let config = GameConfig {
+                board_width,
+                board_height,
+                ..GameConfig::default()
+            };
+            assert!(GameState::with_config(1, Difficulty::Slow, config).is_none());
+        }
+    }
+
+    #[test]
+    fn the_demo_starts_the_usual_length() {
+        let config = GameConfig {
+            initial_length: 8,
+            ..GameConfig::default()
+        };
+        let mut game = GameState::with_config(1, Difficulty::Slow, config).unwrap();
+        assert_eq!(game.snake_length(), 8);
+
+        game.demo_mode = true;
+        game.reset();
+        assert_eq!(body(&game), [(10, 7), (9, 7), (8, 7)]);
+
+        game.demo_mode = false;
+        game.reset();
+        assert_eq!(game.snake_length(), 8);
+    }
+}
@@ -0,0 +1,397 @@
+// Saving a game in progress to a few bytes, and loading it back
+//
+// Everything needed to carry on playing goes in: level, speed and options,
+// score, the snake, the food, the portals and where the random numbers had
+// got to, the seed the game started from, and how new games start (the GameConfig).
+// Bonus fruit and poison are short-lived, so they're left out, and a resumed
+// game starts with the countdown. Only one-player games can be saved.
+//
+// Layout (numbers little-endian, positions as one x byte then one y byte):
+//   version, level, difficulty, flags (bit 0 wrap, bit 1 ghost, bit 2 shrinking), inset,
+//   score (u32), foods eaten (u32), next level score (u32), rng state (u32), seed (u32),
+//   elapsed ms (u32), config initial length, config start direction, direction,
+//   snake length, segments (head first), food count, food positions,
+//   portal count (0 or 2), portal positions
+
+use crate::{
+    Difficulty, Direction, GameConfig, GameState, Position, Rng, BOARD_HEIGHT, BOARD_WIDTH,
+    MAX_FOOD, MAX_SNAKE_LENGTH,
+};
+
+// Bump when the layout changes, so old saves are turned away instead of misread
+const SAVE_VERSION: u8 = 3;
+
+// Biggest a save can be - a buffer this size always fits
+pub const MAX_SAVE_SIZE: usize = 31 + 2 + 2 * MAX_SNAKE_LENGTH + 1 + 2 * MAX_FOOD + 1 + 4;
+
+fn direction_byte(direction: Direction) -> u8 {
+    match direction {
+        Direction::Up => 0,
+        Direction::Down => 1,
+        Direction::Left => 2,
+        Direction::Right => 3,
+    }
+}
+
+// Is `pos` one of player one's segments?
+fn on_snake(game: &GameState, pos: Position) -> bool {
+    game.snakes[0].body.iter().any(|&segment| segment == pos)
+}
+
+fn byte_direction(byte: u8) -> Option<Direction> {
+    match byte {
+        0 => Some(Direction::Up),
+        1 => Some(Direction::Down),
+        2 => Some(Direction::Left),
+        3 => Some(Direction::Right),
+        _ => None,
+    }
+}
+
+// Writes bytes into the caller's buffer, None once it's full
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Writer<'_> {
+    fn u8(&mut self, value: u8) -> Option<()> {
+        *self.buf.get_mut(self.len)? = value;
+        self.len += 1;
+        Some(())
+    }
+
+    fn u32(&mut self, value: u32) -> Option<()> {
+        for byte in value.to_le_bytes() {
+            self.u8(byte)?;
+        }
+        Some(())
+    }
+
+    fn position(&mut self, pos: Position) -> Option<()> {
+        self.u8(pos.x as u8)?;
+        self.u8(pos.y as u8)
+    }
+}
+
+// Reads bytes back out, None if the save ends too soon
+struct Reader<'a> {
+    buf: &'a [u8],
+    next: usize,
+}
+
+impl Reader<'_> {
+    fn u8(&mut self) -> Option<u8> {
+        let value = *self.buf.get(self.next)?;
+        self.next += 1;
+        Some(value)
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        let mut bytes = [0; 4];
+        for byte in bytes.iter_mut() {
+            *byte = self.u8()?;
+        }
+        Some(u32::from_le_bytes(bytes))
+    }
+
+    // Only cells inside the border are any use for a snake or food
+    fn position(&mut self) -> Option<Position> {
+        let pos = Position {
+            x: self.u8()? as usize,
+            y: self.u8()? as usize,
+        };
+        let inside =
+            (1..BOARD_WIDTH - 1).contains(&pos.x) && (1..BOARD_HEIGHT - 1).contains(&pos.y);
+        inside.then_some(pos)
+    }
+
+    // A position that isn't in one of the level's walls either
+    fn open_cell(&mut self, game: &GameState) -> Option<Position> {
+        self.position().filter(|&pos| !game.is_wall(pos))
+    }
+}
+
+impl GameState {
+    // Write the game into `buf`, returning how many bytes it took
+    // Returns 0 if it didn't fit (MAX_SAVE_SIZE always does) or it's a two-player game
+    pub fn serialize(&self, buf: &mut [u8]) -> usize {
+        let mut writer = Writer { buf, len: 0 };
+        match self.write_save(&mut writer) {
+            Some(()) if self.players() == 1 => writer.len,
+            _ => 0,
+        }
+    }
+
+    fn write_save(&self, writer: &mut Writer) -> Option<()> {
+        let snake = &self.snakes[0];
+
+        writer.u8(SAVE_VERSION)?;
+        writer.u8(self.level.min(u8::MAX as usize) as u8)?;
+        writer.u8(match self.difficulty {
+            Difficulty::Slow => 0,
+            Difficulty::Medium => 1,
+            Difficulty::Fast => 2,
+        })?;
+        writer.u8((self.wrap_enabled as u8)
+            | ((self.allow_self_overlap as u8) << 1)
+            | ((self.shrinking_enabled as u8) << 2))?;
+        writer.u8(self.inset as u8)?;
+        writer.u32(self.score)?;
+        writer.u32(self.foods_eaten)?;
+        writer.u32(self.next_level_score)?;
+        writer.u32(self.rng.state)?;
+        writer.u32(self.seed)?;
+        writer.u32(self.elapsed_ms)?;
+        writer.u8(self.config.initial_length.min(u8::MAX as usize) as u8)?;
+        writer.u8(direction_byte(self.config.start_direction))?;
+        writer.u8(direction_byte(snake.direction))?;
+
+        writer.u8(snake.body.len() as u8)?;
+        for &segment in snake.body.iter() {
+            writer.position(segment)?;
+        }
+
+        writer.u8(self.food_count as u8)?;
+        for &food in self.food() {
+            writer.position(food)?;
+        }
+
+        match self.portals {
+            Some(portals) => {
+                writer.u8(2)?;
+                writer.position(portals[0])?;
+                writer.position(portals[1])
+            }
+            None => writer.u8(0),
+        }
+    }
+
+    // Rebuild a game from a save made by serialize
+    // None if it's from another version, cut short, has bytes left over, puts
+    // anything outside the board or in a wall, or has things on top of each other
+    pub fn deserialize(buf: &[u8]) -> Option<GameState> {
+        let mut reader = Reader { buf, next: 0 };
+
+        if reader.u8()? != SAVE_VERSION {
+            return None;
+        }
+        let level = reader.u8()? as usize;
+        let difficulty = match reader.u8()? {
+            0 => Difficulty::Slow,
+            1 => Difficulty::Medium,
+            2 => Difficulty::Fast,
+            _ => return None,
+        };
+        let flags = reader.u8()?;
+        let inset = reader.u8()? as usize;
+        if level == 0 || flags > 0b111 {
+            return None;
+        }
+        let score = reader.u32()?;
+        let foods_eaten = reader.u32()?;
+        let next_level_score = reader.u32()?;
+        let rng_state = reader.u32()?;
+        let seed = reader.u32()?;
+        let elapsed_ms = reader.u32()?;
+        let config = GameConfig {
+            initial_length: reader.u8()? as usize,
+            start_direction: byte_direction(reader.u8()?)?,
+            ..GameConfig::default() // Always the built-in board size
+        };
+        let direction = byte_direction(reader.u8()?)?;
+
+        // The level's walls go up first, so everything read below can be checked against them
+        let mut game = GameState::with_config(seed, difficulty, config)?;
+        game.wrap_enabled = flags & 0b01 != 0;
+        game.allow_self_overlap = flags & 0b10 != 0;
+        game.shrinking_enabled = flags & 0b100 != 0;
+        game.load_level(level);
+        if inset > game.max_inset() {
+            return None;
+        }
+        game.inset = inset;
+
+        let length = reader.u8()? as usize;
+        if length == 0 || length > MAX_SNAKE_LENGTH {
+            return None;
+        }
+        game.snakes[0].body.clear();
+        for _ in 0..length {
+            let segment = reader.open_cell(&game)?;
+            // Only a ghost can cross itself
+            if !game.allow_self_overlap && on_snake(&game, segment) {
+                return None;
+            }
+            let _ = game.snakes[0].body.push_back(segment); // Can't fail, length was checked
+        }
+        game.snakes[0].direction = direction;
+
+        let food_count = reader.u8()? as usize;
+        if food_count > MAX_FOOD {
+            return None;
+        }
+        for i in 0..food_count {
+            let food = reader.open_cell(&game)?;
+            if on_snake(&game, food) || game.food_positions[..i].contains(&food) {
+                return None;
+            }
+            game.food_positions[i] = food;
+        }
+        game.food_count = food_count;
+
+        game.portals = match reader.u8()? {
+            0 => None,
+            2 => Some([reader.open_cell(&game)?, reader.open_cell(&game)?]),
+            _ => return None,
+        };
+        // Snakes go through portals, never stop on one, and food is never put on one
+        if let Some(portals) = game.portals {
+            let taken = |pos: &Position| on_snake(&game, *pos) || game.food().contains(pos);
+            if portals[0] == portals[1] || portals.iter().any(taken) {
+                return None;
+            }
+        }
+
+        // Anything after the end means it isn't one of our saves
+        if reader.next != buf.len() {
+            return None;
+        }
+
+        game.score = score;
+        game.foods_eaten = foods_eaten;
+        game.next_level_score = next_level_score;
+        game.elapsed_ms = elapsed_ms;
+        // Carry on with the same food to come, not the numbers load_level used up
+        game.rng = Rng::new(rng_state);
+        game.update_board();
+
+        // Whatever got past the checks above, the board has to agree with the snake and food
+        game.invariants_hold().then_some(game)
+    }
+}
+
+// Run on the computer, not the board (see the tests in lib.rs)
+#[cfg(test)]
+mod tests {
+    extern crate std;
+    use super::*;
+    use crate::{Difficulty, GameConfig};
+    use std::vec::Vec;
+
+    // Where things are in a save (see the layout at the top)
+    const INSET_BYTE: usize = 4;
+    const FIRST_SEGMENT: usize = 33;
+
+    // A level 2 game (it has walls inside) that the autopilot has played for a while
+    fn played_game() -> GameState {
+        let config = GameConfig {
+            initial_length: 6,
+            ..GameConfig::default()
+        };
+        let mut game = GameState::with_config(31, Difficulty::Medium, config).unwrap();
+        game.set_start_level(2);
+        game.shrinking_enabled = true;
+        game.countdown = 0;
+        for _ in 0..60 {
+            game.enqueue_direction(game.ai_next_direction());
+            game.move_snake();
+        }
+        assert!(!game.game_over);
+        game
+    }
+
+    fn save(game: &GameState) -> Vec<u8> {
+        let mut buf = [0; MAX_SAVE_SIZE];
+        let len = game.serialize(&mut buf);
+        assert!(len > 0);
+        buf[..len].to_vec()
+    }
+
+    #[test]
+    fn a_saved_game_loads_back_the_same() {
+        let game = played_game();
+
+        let loaded = GameState::deserialize(&save(&game)).unwrap();
+
+        assert!(loaded.snake_segments().eq(game.snake_segments()));
+        assert_eq!(loaded.direction(), game.direction());
+        assert_eq!(loaded.food(), game.food());
+        assert_eq!(loaded.portals, game.portals);
+        assert_eq!(loaded.score, game.score);
+        assert_eq!(loaded.level, game.level);
+        assert_eq!(loaded.inset, game.inset);
+        assert_eq!(loaded.seed(), game.seed());
+        assert_eq!(loaded.config, game.config);
+        assert_eq!(loaded.rng.state, game.rng.state);
+        assert!(loaded.shrinking_enabled);
+        assert!(loaded.invariants_hold());
+    }
+
+    #[test]
+    fn a_save_cut_short_or_with_extra_bytes_is_turned_away() {
+        let buf = save(&played_game());
+
+        for len in 0..buf.len() {
+            assert!(
+                GameState::deserialize(&buf[..len]).is_none(),
+                "{} bytes",
+                len
+            );
+        }
+
+        let mut longer = buf.clone();
+        longer.push(0);
+        assert!(GameState::deserialize(&longer).is_none());
+    }
+
+    #[test]
+    fn a_save_from_another_version_is_turned_away() {
+        let mut buf = save(&played_game());
+        buf[0] = SAVE_VERSION - 1;
+
+        assert!(GameState::deserialize(&buf).is_none());
+    }
+
+    #[test]
+    fn a_segment_in_a_wall_is_turned_away() {
+        let mut buf = save(&played_game());
+        // Level 2 has a bar along row 3 from x 4 to 15
+        buf[FIRST_SEGMENT + 2] = 10;
+        buf[FIRST_SEGMENT + 3] = 3;
+
+        assert!(GameState::deserialize(&buf).is_none());
+    }
+
+    #[test]
+    fn walls_closed_in_too_far_are_turned_away() {
+        let game = played_game();
+        let mut buf = save(&game);
+
+        // A 6 long start only lets the walls close in 3 rings
+        assert_eq!(game.max_inset(), 3);
+        buf[INSET_BYTE] = 4;
+
+        assert!(GameState::deserialize(&buf).is_none());
+    }
+
+    #[test]
+    fn things_on_top_of_each_other_are_turned_away() {
+        let game = played_game();
+        let buf = save(&game);
+        let length = game.snake_length();
+        let food_byte = FIRST_SEGMENT + 2 * length + 1;
+
+        // The second segment where the head is
+        let mut repeated = buf.clone();
+        repeated[FIRST_SEGMENT + 2] = buf[FIRST_SEGMENT];
+        repeated[FIRST_SEGMENT + 3] = buf[FIRST_SEGMENT + 1];
+        assert!(GameState::deserialize(&repeated).is_none());
+
+        // The first food on the tail
+        let mut food_on_snake = buf.clone();
+        food_on_snake[food_byte] = buf[food_byte - 3];
+        food_on_snake[food_byte + 1] = buf[food_byte - 2];
+        assert!(GameState::deserialize(&food_on_snake).is_none());
+    }
+}
@@ -0,0 +1,115 @@
+// Taking back the last move
+//
+// Each step starts by copying everything the step can change, so the move can
+// be undone afterwards - even the one that ended the game. The board isn't
+// copied (it's redrawn from the rest), and the segments are kept as byte
+// pairs instead of Positions, so the copy is a couple of hundred bytes rather
+// than the best part of a kilobyte. Only one step back, and only in one-player
+// games - player two would have their move taken back as well.
+
+use crate::{Direction, GameState, Position, COUNTDOWN_START, MAX_FOOD, MAX_SNAKE_LENGTH};
+
+// The game just before a step (which only ever runs while the game is going,
+// so there's no need to keep game_over)
+#[derive(Clone)]
+pub(crate) struct Snapshot {
+    body: [(u8, u8); MAX_SNAKE_LENGTH], // Head first, `length` of them used
+    length: usize,
+    direction: Direction,
+    score: u32,
+    foods_eaten: u32,
+    combo: u32,
+    ticks_since_last_food: u32,
+    food_positions: [Position; MAX_FOOD],
+    food_count: usize,
+    bonus: Option<(Position, u32)>,
+    poison: Option<(Position, u32)>,
+    portals: Option<[Position; 2]>,
+    level: usize, // A step can reach the next level, which changes all of the below
+    next_level_score: u32,
+    inset: usize,
+    rng_state: u32, // So the food eaten comes back in the same place if it's eaten again
+}
+
+impl GameState {
+    // Remember how things are before a step (called by move_snake)
+    pub(crate) fn take_snapshot(&mut self) {
+        if self.players() != 1 {
+            self.prev_state = None;
+            return;
+        }
+
+        let snake = &self.snakes[0];
+        let mut body = [(0, 0); MAX_SNAKE_LENGTH];
+        for (slot, segment) in body.iter_mut().zip(snake.body.iter()) {
+            *slot = (segment.x as u8, segment.y as u8);
+        }
+
+        self.prev_state = Some(Snapshot {
+            body,
+            length: snake.body.len(),
+            direction: snake.direction,
+            score: self.score,
+            foods_eaten: self.foods_eaten,
+            combo: self.combo,
+            ticks_since_last_food: self.ticks_since_last_food,
+            food_positions: self.food_positions,
+            food_count: self.food_count,
+            bonus: self.bonus,
+            poison: self.poison,
+            portals: self.portals,
+            level: self.level,
+            next_level_score: self.next_level_score,
+            inset: self.inset,
+            rng_state: self.rng.state,
+        });
+    }
+
+    // Is there a move to take back?
+    pub fn can_undo(&self) -> bool {
+        self.prev_state.is_some()
+    }
+
+    // Put the game back how it was before the last step, returning false if there's nothing to undo
+    // A game that just ended carries on, after a countdown to pick a new direction.
+    // Turns queued up are dropped, time keeps counting and the high score stays.
+    pub fn restore_previous(&mut self) -> bool {
+        let Some(snapshot) = self.prev_state.take() else {
+            return false;
+        };
+
+        let snake = &mut self.snakes[0];
+        snake.body.clear();
+        for &(x, y) in &snapshot.body[..snapshot.length] {
+            let position = Position {
+                x: x as usize,
+                y: y as usize,
+            };
+            let _ = snake.body.push_back(position); // Can't fail, it came out of the same Deque
+        }
+        snake.direction = snapshot.direction;
+        snake.queue_len = 0;
+
+        self.score = snapshot.score;
+        self.foods_eaten = snapshot.foods_eaten;
+        self.combo = snapshot.combo;
+        self.ticks_since_last_food = snapshot.ticks_since_last_food;
+        self.food_positions = snapshot.food_positions;
+        self.food_count = snapshot.food_count;
+        self.bonus = snapshot.bonus;
+        self.poison = snapshot.poison;
+        self.portals = snapshot.portals;
+        self.level = snapshot.level;
+        self.next_level_score = snapshot.next_level_score;
+        self.inset = snapshot.inset;
+        self.rng.state = snapshot.rng_state;
+
+        self.game_over = false;
+        self.game_won = false;
+        self.crashed = [false; 2];
+        self.countdown = COUNTDOWN_START;
+        self.update_board();
+
+        true
+    }
+}
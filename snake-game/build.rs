@@ -0,0 +1,7 @@
+// defmt keeps its log strings in a section of their own, set up by its linker script
+// Only wanted with the debug-log feature - the linker can't find defmt.x without it
+fn main() {
+    if std::env::var_os("CARGO_FEATURE_DEBUG_LOG").is_some() {
+        println!("cargo:rustc-link-arg-bins=-Tdefmt.x");
+    }
+}
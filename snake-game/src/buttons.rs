@@ -0,0 +1,108 @@
+// Push buttons, so the game can be played without a terminal
+// Each button is wired between its pin and GND - the internal pull-up keeps
+// the pin high until it's pressed. Unconnected pins just read as "not pressed".
+
+use stm32f4xx_hal::gpio::{ErasedPin, Input, PB12, PB13, PB14, PB15, PC13};
+
+// Pin assignments - to remap a button change its alias here, then pass the
+// matching gpiob/gpioc pin to Buttons::new in main
+pub type UpPin = PB12;
+pub type DownPin = PB13;
+pub type LeftPin = PB14;
+pub type RightPin = PB15;
+pub type RestartPin = PC13; // The blue user button on the Nucleo
+
+// A button has to read the same for this many frames before we believe it
+const DEBOUNCE_FRAMES: u8 = 2;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Button {
+    Up,
+    Down,
+    Left,
+    Right,
+    Restart,
+}
+
+// Same order as the pins passed to Buttons::new
+const BUTTON_ORDER: [Button; 5] = [
+    Button::Up,
+    Button::Down,
+    Button::Left,
+    Button::Right,
+    Button::Restart,
+];
+
+// Debounce state for one button
+#[derive(Clone, Copy)]
+struct Debounce {
+    last_sample: bool, // What the pin read last frame
+    same_count: u8,    // Frames in a row it has read that
+    pressed: bool,     // The accepted (debounced) state
+}
+
+impl Debounce {
+    fn new() -> Self {
+        Debounce {
+            last_sample: false,
+            same_count: 0,
+            pressed: false,
+        }
+    }
+
+    // Feed in this frame's sample, returns true when a new press is accepted
+    fn update(&mut self, sample: bool) -> bool {
+        if sample == self.last_sample {
+            if self.same_count < DEBOUNCE_FRAMES {
+                self.same_count += 1;
+            }
+        } else {
+            self.last_sample = sample;
+            self.same_count = 1;
+        }
+
+        if self.same_count >= DEBOUNCE_FRAMES && sample != self.pressed {
+            self.pressed = sample;
+            return sample;
+        }
+
+        false
+    }
+}
+
+pub struct Buttons {
+    pins: [ErasedPin<Input>; 5],
+    debounce: [Debounce; 5],
+}
+
+impl Buttons {
+    pub fn new(
+        up: UpPin,
+        down: DownPin,
+        left: LeftPin,
+        right: RightPin,
+        restart: RestartPin,
+    ) -> Self {
+        Buttons {
+            pins: [
+                up.into_pull_up_input().erase(),
+                down.into_pull_up_input().erase(),
+                left.into_pull_up_input().erase(),
+                right.into_pull_up_input().erase(),
+                restart.into_pull_up_input().erase(),
+            ],
+            debounce: [Debounce::new(); 5],
+        }
+    }
+
+    // Sample every button (call once per frame) and report each new press
+    pub fn poll(&mut self, mut on_press: impl FnMut(Button)) {
+        for ((pin, debounce), button) in self.pins.iter().zip(&mut self.debounce).zip(BUTTON_ORDER)
+        {
+            // Pressed pulls the pin low
+            if debounce.update(pin.is_low()) {
+                on_press(button);
+            }
+        }
+    }
+}
@@ -0,0 +1,55 @@
+// Attract mode - after the game over screen sits for a while, a recorded run
+// plays by itself until someone presses a key or button
+// The recording is a list of turns, each followed by a number of steps. It's
+// replayed with a fixed food seed, so it goes the same way every time.
+
+use snake_core::Direction;
+
+// How long (in ms) the game over screen waits before the demo starts
+pub const DEMO_IDLE_MS: u32 = 10_000;
+
+// Food seed for the demo game
+pub const DEMO_SEED: u32 = 0x5EED;
+
+// Laps around the inside of the border, clear of every level's walls
+// The snake starts at (10, 7) heading right
+pub const DEMO_MOVES: &[(Direction, u32)] = &[
+    (Direction::Right, 8),
+    (Direction::Up, 6),
+    (Direction::Left, 17),
+    (Direction::Down, 12),
+    (Direction::Right, 17),
+    (Direction::Up, 12),
+    (Direction::Left, 17),
+    (Direction::Down, 12),
+    (Direction::Right, 17),
+    (Direction::Up, 6),
+];
+
+pub struct Demo {
+    moves: &'static [(Direction, u32)],
+    next: usize,     // Index of the next turn to take
+    steps_left: u32, // Steps before that turn
+}
+
+impl Demo {
+    pub fn new(moves: &'static [(Direction, u32)]) -> Self {
+        Demo {
+            moves,
+            next: 0,
+            steps_left: 0,
+        }
+    }
+
+    // Direction for this step, or None once the recording has run out
+    pub fn next_direction(&mut self) -> Option<Direction> {
+        while self.steps_left == 0 {
+            let &(_, steps) = self.moves.get(self.next)?;
+            self.steps_left = steps;
+            self.next += 1;
+        }
+
+        self.steps_left -= 1;
+        Some(self.moves[self.next - 1].0)
+    }
+}
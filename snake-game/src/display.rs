@@ -0,0 +1,225 @@
+// OLED display - draws the game on an SSD1306 128x64 panel over I2C1
+// (PB8 = SCL, PB9 = SDA). Only built with the "oled" feature.
+// The whole frame is drawn into a RAM buffer, then sent in one flush.
+
+use crate::renderer::{countdown_label, Renderer};
+use core::fmt::Write;
+use embedded_graphics::{
+    mono_font::{ascii::FONT_6X10, MonoTextStyle},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{Circle, Line, PrimitiveStyle, Rectangle},
+    text::{Alignment, Baseline, Text, TextStyleBuilder},
+};
+use heapless::String;
+use snake_core::{Cell, GameState, BOARD_HEIGHT, BOARD_WIDTH};
+use ssd1306::{mode::BufferedGraphicsMode, prelude::*, I2CDisplayInterface, Ssd1306};
+use stm32f4xx_hal::{i2c::I2c, pac::I2C1};
+
+// Each board cell is a 4x4 pixel square, so the 20x15 board takes 80x60 pixels
+// and leaves a column on the right for the score
+const CELL_SIZE: u32 = 4;
+const BOARD_TOP: i32 = 2; // Centers the 60 pixel tall board on the 64 pixel screen
+const PANEL_LEFT: i32 = 84; // Text column, just right of the board
+const LINE_HEIGHT: i32 = 10; // FONT_6X10 rows
+
+type Display =
+    Ssd1306<I2CInterface<I2c<I2C1>>, DisplaySize128x64, BufferedGraphicsMode<DisplaySize128x64>>;
+
+pub struct Oled {
+    display: Display,
+    enabled: bool, // False if the panel didn't answer at startup
+}
+
+impl Oled {
+    // No panel wired up (or a bad connection) just leaves the display turned off,
+    // the game carries on without it
+    pub fn new(i2c: I2c<I2C1>) -> Self {
+        let interface = I2CDisplayInterface::new(i2c);
+        let mut display = Ssd1306::new(interface, DisplaySize128x64, DisplayRotation::Rotate0)
+            .into_buffered_graphics_mode();
+        let enabled = display.init().is_ok();
+
+        Oled { display, enabled }
+    }
+
+    // Each kind of cell gets its own shape so they're easy to tell apart
+    fn draw_cell(&mut self, game: &GameState, row: usize, col: usize) {
+        let corner = Point::new(
+            col as i32 * CELL_SIZE as i32,
+            BOARD_TOP + row as i32 * CELL_SIZE as i32,
+        );
+        let filled = PrimitiveStyle::with_fill(BinaryColor::On);
+        let outline = PrimitiveStyle::with_stroke(BinaryColor::On, 1);
+        let on_border = row == 0 || row == BOARD_HEIGHT - 1 || col == 0 || col == BOARD_WIDTH - 1;
+
+        let _ = match game.board[row][col] {
+            Cell::Empty => Ok(()),
+            // Wrap mode border is a portal - just a dot in the middle of the cell
+            Cell::Wall if on_border && game.wrap_enabled => {
+                Rectangle::new(corner + Point::new(1, 1), Size::new(1, 1))
+                    .into_styled(filled)
+                    .draw(&mut self.display)
+            }
+            // Wall - solid block
+            Cell::Wall => Rectangle::new(corner, Size::new(CELL_SIZE, CELL_SIZE))
+                .into_styled(filled)
+                .draw(&mut self.display),
+            // Body - slightly smaller block so the segments show a gap
+            Cell::Snake => Rectangle::new(corner, Size::new(CELL_SIZE - 1, CELL_SIZE - 1))
+                .into_styled(filled)
+                .draw(&mut self.display),
+            // Head - hollow square
+            Cell::SnakeHead => Rectangle::new(corner, Size::new(CELL_SIZE, CELL_SIZE))
+                .into_styled(outline)
+                .draw(&mut self.display),
+            // Player two's body - hollow block, and their head - a plus sign
+            Cell::Snake2 => Rectangle::new(corner, Size::new(CELL_SIZE - 1, CELL_SIZE - 1))
+                .into_styled(outline)
+                .draw(&mut self.display),
+            Cell::Snake2Head => {
+                let far = CELL_SIZE as i32 - 1;
+                let _ = Line::new(corner + Point::new(0, 1), corner + Point::new(far, 1))
+                    .into_styled(outline)
+                    .draw(&mut self.display);
+                Line::new(corner + Point::new(1, 0), corner + Point::new(1, far))
+                    .into_styled(outline)
+                    .draw(&mut self.display)
+            }
+            // Food - round dot
+            Cell::Food => Circle::new(corner, CELL_SIZE)
+                .into_styled(filled)
+                .draw(&mut self.display),
+            // Bonus - hollow ring, blinking once it's about to go
+            Cell::Bonus if game.bonus_expiring() && game.ticks_elapsed % 2 == 1 => Ok(()),
+            Cell::Bonus => Circle::new(corner, CELL_SIZE)
+                .into_styled(outline)
+                .draw(&mut self.display),
+            // Poison - a cross
+            Cell::Poison => {
+                let far = CELL_SIZE as i32 - 1;
+                let _ = Line::new(corner, corner + Point::new(far, far))
+                    .into_styled(outline)
+                    .draw(&mut self.display);
+                Line::new(corner + Point::new(far, 0), corner + Point::new(0, far))
+                    .into_styled(outline)
+                    .draw(&mut self.display)
+            }
+            // Portal - small dot in the middle of the cell
+            Cell::Portal => Rectangle::new(corner + Point::new(1, 1), Size::new(2, 2))
+                .into_styled(filled)
+                .draw(&mut self.display),
+        };
+    }
+
+    // Score, high score, level, length and combo down the right hand side
+    fn draw_panel(&mut self, game: &GameState) {
+        let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+
+        // Numbers are formatted into a small fixed buffer (u32 is at most 10 digits)
+        let mut text: String<16> = String::new();
+        let mut line = |y: i32, label: &str, value: u32, display: &mut Display| {
+            text.clear();
+            let _ = write!(text, "{}{}", label, value);
+            let _ = Text::with_baseline(&text, Point::new(PANEL_LEFT, y), style, Baseline::Top)
+                .draw(display);
+        };
+
+        // Two-player games show both scores instead of the high score
+        if game.players() == 2 {
+            line(0, "P1:", game.score, &mut self.display);
+            line(LINE_HEIGHT, "P2:", game.player2_score, &mut self.display);
+        } else {
+            line(0, "S:", game.score, &mut self.display);
+            line(LINE_HEIGHT, "H:", game.high_score, &mut self.display);
+        }
+        line(2 * LINE_HEIGHT, "Lv:", game.level as u32, &mut self.display);
+        line(
+            3 * LINE_HEIGHT,
+            "Ln:",
+            game.snake_length() as u32,
+            &mut self.display,
+        );
+
+        // Practice mode reminder on the spare line
+        if game.allow_self_overlap {
+            let _ = Text::with_baseline(
+                "GHOST",
+                Point::new(PANEL_LEFT, 4 * LINE_HEIGHT),
+                style,
+                Baseline::Top,
+            )
+            .draw(&mut self.display);
+        }
+
+        // Nothing to announce mid-game, so that's where the combo multiplier goes
+        let mut combo: String<4> = String::new();
+        let banner = if game.demo_mode {
+            "DEMO"
+        } else if game.game_won {
+            "WIN!"
+        } else if game.game_over && game.players() == 2 {
+            match game.winner() {
+                Some(0) => "P1 WON",
+                Some(_) => "P2 WON",
+                None => "DRAW",
+            }
+        } else if game.game_over {
+            "OVER"
+        } else if game.paused {
+            "PAUSE"
+        } else if game.combo > 1 {
+            let _ = write!(combo, "x{}", game.combo);
+            &combo
+        } else {
+            ""
+        };
+        let _ = Text::with_baseline(
+            banner,
+            Point::new(PANEL_LEFT, 5 * LINE_HEIGHT),
+            style,
+            Baseline::Top,
+        )
+        .draw(&mut self.display);
+    }
+}
+
+impl Renderer for Oled {
+    // Draw the whole frame into the buffer, then send it to the display
+    // Drawing into the buffer can't fail, only the flush talks to the hardware
+    fn draw(&mut self, game: &GameState) {
+        if !self.enabled {
+            return;
+        }
+
+        self.display.clear_buffer();
+
+        for row in 0..BOARD_HEIGHT {
+            for col in 0..BOARD_WIDTH {
+                self.draw_cell(game, row, col);
+            }
+        }
+
+        // Countdown in a cleared box over the middle of the board
+        if let Some(label) = countdown_label(game) {
+            let center = Point::new(
+                (BOARD_WIDTH as u32 * CELL_SIZE / 2) as i32,
+                BOARD_TOP + (BOARD_HEIGHT as u32 * CELL_SIZE / 2) as i32,
+            );
+            let _ = Rectangle::with_center(center, Size::new(24, 14))
+                .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+                .draw(&mut self.display);
+            let style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+            let centered = TextStyleBuilder::new()
+                .alignment(Alignment::Center)
+                .baseline(Baseline::Middle)
+                .build();
+            let _ = Text::with_text_style(label, center, style, centered).draw(&mut self.display);
+        }
+
+        self.draw_panel(game);
+
+        // A missing or unplugged display shouldn't stop the game
+        let _ = self.display.flush();
+    }
+}
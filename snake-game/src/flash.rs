@@ -0,0 +1,46 @@
+// High score storage in the last sector of the internal flash
+//
+// Flash bits can only be programmed from 1 to 0, so the sector has to be erased
+// (back to all 0xFF) before every write. Each sector only survives about 10,000
+// erases, so we only write when the score actually beat the stored one.
+// memory.x stops the linker from putting the program in this sector.
+
+use stm32f4xx_hal::flash::{FlashExt, FlashSector};
+use stm32f4xx_hal::pac::FLASH;
+
+// What an erased word reads as - nothing saved yet
+const ERASED_WORD: u32 = 0xFFFF_FFFF;
+
+// The sector holding the score (last one on the chip)
+fn score_sector(flash: &FLASH) -> FlashSector {
+    flash.sector(flash.len() - 1).unwrap()
+}
+
+// Read the saved high score, 0 if there isn't one yet
+pub fn read_high_score(flash: &FLASH) -> u32 {
+    let offset = score_sector(flash).offset;
+    let bytes = &flash.read()[offset..offset + 4];
+    let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+
+    if word == ERASED_WORD {
+        0
+    } else {
+        word
+    }
+}
+
+// Save a new high score (skipped if it isn't better than the stored one)
+// Erasing a 128K sector takes a second or two, so only call this between games
+pub fn write_high_score(flash: &mut FLASH, score: u32) {
+    if score <= read_high_score(flash) {
+        return;
+    }
+
+    let sector = score_sector(flash);
+    // Flash locks itself again when `unlocked` goes out of scope
+    let mut unlocked = flash.unlocked();
+    if unlocked.erase(sector.number).is_ok() {
+        // Nothing useful to do if this fails - the old score is already gone
+        let _ = unlocked.program(sector.offset, score.to_le_bytes().iter());
+    }
+}
@@ -0,0 +1,19 @@
+// Where the game's controls come from - the keys are decoded in snake-core
+// (see its input.rs), the buttons and joystick are read here in the firmware
+
+use crate::buttons::Button;
+use snake_core::Direction;
+pub use snake_core::{GameInput, InputSource, UartInput};
+
+// Buttons map straight across (they're sampled once a frame in main)
+impl From<Button> for GameInput {
+    fn from(button: Button) -> Self {
+        match button {
+            Button::Up => GameInput::Direction(Direction::Up),
+            Button::Down => GameInput::Direction(Direction::Down),
+            Button::Left => GameInput::Direction(Direction::Left),
+            Button::Right => GameInput::Direction(Direction::Right),
+            Button::Restart => GameInput::Restart,
+        }
+    }
+}
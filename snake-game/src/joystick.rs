@@ -0,0 +1,82 @@
+// Two-axis analog joystick on ADC1 - X on PA0, Y on PA1 (A0/A1 on the Arduino header)
+// Only built with the "joystick" feature, since unconnected analog pins float
+// and would steer the snake at random.
+//
+// A centered stick reads about half scale on both axes. Once it's pushed past
+// the deadzone, the axis pushed furthest wins, so diagonals pick one direction.
+
+use crate::input::{GameInput, InputSource};
+use snake_core::Direction;
+use stm32f4xx_hal::{
+    adc::{
+        config::{AdcConfig, SampleTime},
+        Adc,
+    },
+    gpio::{Analog, PA0, PA1},
+    pac::ADC1,
+};
+
+const ADC_CENTER: i32 = 2048; // Readings are 12 bit (0 to 4095)
+
+// How far from center (in ADC counts) the stick has to go before it counts
+// Raise this if the snake turns with the stick left alone
+const DEADZONE: i32 = 800;
+
+pub struct Joystick {
+    adc: Adc<ADC1>,
+    x_pin: PA0<Analog>,
+    y_pin: PA1<Analog>,
+    last: Option<Direction>, // Where the stick pointed last frame (None = centered)
+}
+
+impl Joystick {
+    pub fn new(adc1: ADC1, x_pin: PA0, y_pin: PA1) -> Self {
+        Joystick {
+            adc: Adc::adc1(adc1, true, AdcConfig::default()),
+            x_pin: x_pin.into_analog(),
+            y_pin: y_pin.into_analog(),
+            last: None,
+        }
+    }
+
+    // Sample both axes (call once per frame)
+    // Returns a direction only when the stick moves to a new one, so holding it
+    // doesn't fill up the turn queue
+    fn read(&mut self) -> Option<Direction> {
+        let x = self.adc.convert(&self.x_pin, SampleTime::Cycles_480) as i32 - ADC_CENTER;
+        let y = self.adc.convert(&self.y_pin, SampleTime::Cycles_480) as i32 - ADC_CENTER;
+
+        let direction = if x.abs() < DEADZONE && y.abs() < DEADZONE {
+            None // Centered (or just jitter)
+        } else if x.abs() > y.abs() {
+            Some(if x < 0 {
+                Direction::Left
+            } else {
+                Direction::Right
+            })
+        } else {
+            // Most modules read low with the stick pushed away from you
+            Some(if y < 0 {
+                Direction::Up
+            } else {
+                Direction::Down
+            })
+        };
+
+        let changed = direction != self.last;
+        self.last = direction;
+
+        if changed {
+            direction
+        } else {
+            None
+        }
+    }
+}
+
+// Only steers - a push is never a restart
+impl InputSource for Joystick {
+    fn poll(&mut self) -> Option<GameInput> {
+        self.read().map(GameInput::Direction)
+    }
+}
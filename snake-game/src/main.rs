@@ -1,399 +1,726 @@
 #![no_std]
 #![no_main]
 
-use cortex_m_rt::entry;
-use nb::block;
+// Without a renderer there'd be nothing to watch
+#[cfg(not(any(feature = "uart-render", feature = "oled", feature = "state-packets")))]
+compile_error!("enable the uart-render, oled or state-packets feature");
+
+// The USART2 baud rate comes from exactly one baud-* feature
+#[cfg(not(any(
+    feature = "baud-9600",
+    feature = "baud-57600",
+    feature = "baud-115200",
+    feature = "baud-230400"
+)))]
+compile_error!("enable one of the baud-9600, baud-57600, baud-115200 or baud-230400 features");
+#[cfg(any(
+    all(feature = "baud-9600", feature = "baud-57600"),
+    all(feature = "baud-9600", feature = "baud-115200"),
+    all(feature = "baud-9600", feature = "baud-230400"),
+    all(feature = "baud-57600", feature = "baud-115200"),
+    all(feature = "baud-57600", feature = "baud-230400"),
+    all(feature = "baud-115200", feature = "baud-230400")
+))]
+compile_error!(
+    "enable only one baud-* feature (use --no-default-features to drop the default baud-115200)"
+);
+
+mod buttons;
+mod demo;
+#[cfg(feature = "oled")]
+mod display;
+mod flash;
+mod input;
+#[cfg(feature = "joystick")]
+mod joystick;
+mod menu;
+#[cfg(feature = "state-packets")]
+mod protocol;
+mod renderer;
+mod sound;
+mod terminal;
+
+use core::cell::RefCell;
+use cortex_m::interrupt::Mutex;
+use demo::{Demo, DEMO_MOVES, DEMO_SEED};
+use heapless::spsc::Queue;
+use input::GameInput;
+use menu::MenuChoice;
+// A panic stops the game where it is - with debug-log it's printed over RTT first
+#[cfg(not(feature = "debug-log"))]
 use panic_halt as _;
-use stm32f4xx_hal::{
-    pac,
-    prelude::*,
-    serial::{config::Config, Serial},
-};
-
-// Game constants
-const BOARD_WIDTH: usize = 20;
-const BOARD_HEIGHT: usize = 15;
-const MAX_SNAKE_LENGTH: usize = 100;
-
-// Game cell types
-#[derive(Clone, Copy, PartialEq)]
-enum Cell {
-    Empty,
-    Wall,
-    Snake,
-    Food,
+#[cfg(feature = "debug-log")]
+use panic_probe as _;
+// Sends the game's defmt logs (see snake-core) to the debug probe over RTT
+#[cfg(feature = "debug-log")]
+use defmt_rtt as _;
+use snake_core::{GameConfig, GameState};
+use terminal::Terminal;
+
+// Clock constants
+// The Nucleo's ST-LINK feeds an 8 MHz clock into the HSE pin (no crystal fitted)
+const HSE_FREQ_MHZ: u32 = 8;
+const SYSCLK_MHZ: u32 = 84; // PLL output, APB1 (and USART2) runs at half this
+
+// Watchdog - resets the chip if frames stop being drawn for this long
+// Frames are 300 ms at most, but saving a high score erases a 128 KB flash
+// sector, which can take up to 4 s at the HAL's 8-bit write size
+const WATCHDOG_TIMEOUT_MS: u32 = 5_000;
+
+// Serial baud rate, picked by a baud-* feature
+// Slower is more forgiving of long cables, but a full redraw takes longer to send
+#[cfg(feature = "baud-9600")]
+const BAUD_RATE: u32 = 9_600;
+#[cfg(feature = "baud-57600")]
+const BAUD_RATE: u32 = 57_600;
+#[cfg(feature = "baud-115200")]
+const BAUD_RATE: u32 = 115_200;
+#[cfg(feature = "baud-230400")]
+const BAUD_RATE: u32 = 230_400;
+
+// Received bytes waiting for the game tick (the queue holds one less than its size)
+const RX_QUEUE_SIZE: usize = 32;
+
+// Filled by the USART2 receive task, emptied by the game tick and the menu
+static RX_QUEUE: Mutex<RefCell<Queue<u8, RX_QUEUE_SIZE>>> = Mutex::new(RefCell::new(Queue::new()));
+
+// Same again for player two's keyboard on USART1 (two-player mode)
+static PLAYER2_RX_QUEUE: Mutex<RefCell<Queue<u8, RX_QUEUE_SIZE>>> =
+    Mutex::new(RefCell::new(Queue::new()));
+
+// Queue full - drop the oldest byte to make room, the receive tasks must never wait
+// (the newest keys are the ones the player meant most recently)
+fn queue_received_byte(queue: &mut Queue<u8, RX_QUEUE_SIZE>, byte: u8) {
+    if queue.is_full() {
+        queue.dequeue();
+    }
+    let _ = queue.enqueue(byte);
 }
 
-// Position on the game board
-#[derive(Clone, Copy, PartialEq)]
-struct Position {
-    x: usize,
-    y: usize,
+// Take the oldest received byte, if there is one
+fn next_received_byte() -> Option<u8> {
+    cortex_m::interrupt::free(|cs| RX_QUEUE.borrow(cs).borrow_mut().dequeue())
 }
 
-// Snake movement direction
-#[derive(Clone, Copy, PartialEq)]
-enum Direction {
-    Up,
-    Down,
-    Left,
-    Right,
+// Same for player two's keys
+fn next_player2_byte() -> Option<u8> {
+    cortex_m::interrupt::free(|cs| PLAYER2_RX_QUEUE.borrow(cs).borrow_mut().dequeue())
 }
 
-// Main game state
-struct GameState {
-    // Game board - 2D array of cells
-    board: [[Cell; BOARD_WIDTH]; BOARD_HEIGHT],
-
-    // Snake data
-    snake_body: [Position; MAX_SNAKE_LENGTH], // Snake segments
-    snake_length: usize,                      // Current snake length
-    snake_direction: Direction,               // Current movement direction
+// Microseconds into the current frame - just read, so the frame timer isn't disturbed
+fn frame_timer_count() -> u32 {
+    // Safe: nothing else touches TIM2's count, and reading it changes nothing
+    unsafe { (*stm32f4xx_hal::pac::TIM2::ptr()).cnt().read().bits() }
+}
 
-    // Food position
-    food_position: Position,
+// Start a new game and redraw everything
+fn restart_game(terminal: &mut Terminal, game: &mut GameState) {
+    game.reset();
+    terminal.needs_full_redraw = true;
+    let _ = terminal.send_string(b"Game restarted!\r\n"); // Only a message, fine to lose
+}
 
-    // Game status
-    score: u32,
-    game_over: bool,
+// Play the recorded demo from the start
+fn start_demo(demo: &mut Demo, terminal: &mut Terminal, game: &mut GameState) {
+    *demo = Demo::new(DEMO_MOVES);
+    game.demo_mode = true;
+    game.reset_with_seed(DEMO_SEED);
+    terminal.needs_full_redraw = true;
 }
 
-impl GameState {
-    fn new() -> Self {
-        let mut game = GameState {
-            board: [[Cell::Empty; BOARD_WIDTH]; BOARD_HEIGHT],
-            snake_body: [Position { x: 0, y: 0 }; MAX_SNAKE_LENGTH],
-            snake_length: 3,
-            snake_direction: Direction::Right,
-            food_position: Position { x: 15, y: 7 },
-            score: 0,
-            game_over: false,
-        };
-
-        // Initialize snake in the middle of the board
-        game.snake_body[0] = Position { x: 10, y: 7 }; // Head
-        game.snake_body[1] = Position { x: 9, y: 7 }; // Body
-        game.snake_body[2] = Position { x: 8, y: 7 }; // Tail
-
-        // Set up the board borders
-        game.update_board();
-
-        game
+// Act on one input from any source (the menu is opened by the game tick)
+fn handle_input(input: GameInput, terminal: &mut Terminal, game: &mut GameState) {
+    // Game over - any input starts a new game and does nothing else,
+    // so it can't turn the fresh snake as well
+    // Except undo, which takes back the move that ended it (while there is one)
+    if game.game_over || game.game_won {
+        if input != GameInput::Undo || !game.restore_previous() {
+            restart_game(terminal, game);
+        }
+        return;
     }
 
-    fn update_board(&mut self) {
-        // Clear the board
-        for row in 0..BOARD_HEIGHT {
-            for col in 0..BOARD_WIDTH {
-                self.board[row][col] = Cell::Empty;
-            }
+    match input {
+        GameInput::Direction(direction) => game.enqueue_direction(direction),
+        GameInput::Player2Direction(direction) => game.enqueue_player_direction(1, direction),
+        GameInput::Restart => restart_game(terminal, game),
+        GameInput::Pause => game.toggle_pause(),
+        GameInput::ToggleWrap => game.toggle_wrap(),
+        GameInput::NextLevel => game.next_start_level(),
+        GameInput::ToggleAutopilot => game.autopilot = !game.autopilot,
+        GameInput::ToggleAssist => game.assist = !game.assist,
+        GameInput::Undo => {
+            game.restore_previous();
         }
-
-        // Add walls around the border
-        for col in 0..BOARD_WIDTH {
-            self.board[0][col] = Cell::Wall; // Top wall
-            self.board[BOARD_HEIGHT - 1][col] = Cell::Wall; // Bottom wall
+        GameInput::Quit | GameInput::Menu | GameInput::Other => {
+            // Quitting (and the menu) is handled by the game tick, the rest do nothing mid-game
         }
+    }
+}
 
-        for row in 0..BOARD_HEIGHT {
-            self.board[row][0] = Cell::Wall; // Left wall
-            self.board[row][BOARD_WIDTH - 1] = Cell::Wall; // Right wall
-        }
+// Set up a new game with the options picked in the menu, with its food from `seed`
+fn apply_choice(game: &mut GameState, choice: &MenuChoice, seed: u32) {
+    // The start length is part of the game's config, which is fixed when it's made,
+    // so this is a whole new game - keeping the high score and the c/h toggles
+    let config = GameConfig {
+        initial_length: choice.start_length,
+        ..GameConfig::default()
+    };
+    if let Some(mut fresh) = GameState::with_config(seed, choice.difficulty, config) {
+        fresh.high_score = game.high_score;
+        fresh.autopilot = game.autopilot;
+        fresh.assist = game.assist;
+        *game = fresh;
+    }
 
-        // Place snake on board
-        for i in 0..self.snake_length {
-            let pos = self.snake_body[i];
-            self.board[pos.y][pos.x] = Cell::Snake;
-        }
+    game.difficulty = choice.difficulty;
+    game.wrap_enabled = choice.wrap_enabled;
+    game.shrinking_enabled = choice.shrinking_enabled;
+    game.allow_self_overlap = choice.ghost_enabled;
+    game.two_player = choice.two_player;
+    game.set_start_level(choice.start_level);
+    // Last, so nothing above can start the game over with another seed
+    game.reset_with_seed(seed);
+}
 
-        // Place food on board
-        self.board[self.food_position.y][self.food_position.x] = Cell::Food;
+// The game runs as RTIC tasks, highest priority first:
+// - usart2_rx / usart1_rx (3): queue each received byte, so no key is missed
+// - tick (2, TIM2): one game step - input, demo, countdown, then the move
+// - sound_tick (2, TIM5): ends or changes notes
+// - render / menu (1): draw the frame, or run the options menu
+// - idle (0): sleeps until the next interrupt
+#[rtic::app(device = stm32f4xx_hal::pac, dispatchers = [SPI1])]
+mod app {
+    use super::*;
+    use crate::buttons::Buttons;
+    use crate::demo::DEMO_IDLE_MS;
+    use crate::input::{InputSource, UartInput};
+    use crate::menu::Menu;
+    use crate::renderer::Renderer;
+    use crate::sound::Sound;
+    use snake_core::StepEvent;
+    #[cfg(feature = "oled")]
+    use stm32f4xx_hal::i2c::I2c;
+    use stm32f4xx_hal::{
+        dma::StreamsTuple,
+        gpio::{Output, PA5},
+        pac,
+        prelude::*,
+        serial::{
+            config::{Config, DmaConfig},
+            Rx, Serial,
+        },
+        timer::{CounterUs, Event},
+        watchdog::IndependentWatchdog,
+    };
+
+    // Optional hardware is a stand-in () when its feature is off - RTIC can't leave
+    // a resource out of a task's list, only out of these structs
+    #[cfg(feature = "joystick")]
+    type Joystick = crate::joystick::Joystick;
+    #[cfg(not(feature = "joystick"))]
+    type Joystick = ();
+    #[cfg(feature = "oled")]
+    type Oled = crate::display::Oled;
+    #[cfg(not(feature = "oled"))]
+    type Oled = ();
+
+    // Used by more than one task - RTIC hands them out through lock()
+    #[shared]
+    struct Shared {
+        game: GameState,
+        terminal: Terminal,
+        sound: Sound,
+        uart_input: UartInput, // Its arrow key parser is needed by the menu too
+        watchdog: IndependentWatchdog,
+        menu_open: bool, // The tick leaves the game (and the keys) alone while it's set
     }
 
-    // Move the snake forward one step
-    fn move_snake(&mut self) {
-        if self.game_over {
-            return; // Don't move if game is over
-        }
+    // Each belongs to a single task
+    #[local]
+    struct Local {
+        uart_rx: Rx<pac::USART2>,
+        player2_rx: Rx<pac::USART1>,
+        frame_timer: CounterUs<pac::TIM2>,
+        buttons: Buttons,
+        joystick: Joystick,
+        player2_input: UartInput,
+        led: PA5<Output>,
+        flash: pac::FLASH,
+        saved_high_score: u32,
+        step_ms: u32,
+        demo: Demo,
+        oled: Oled,
+        choice: MenuChoice,
+    }
 
-        // Calculate new head position based on current direction
-        let current_head = self.snake_body[0];
-        let new_head = match self.snake_direction {
-            Direction::Up => Position {
-                x: current_head.x,
-                y: current_head.y.saturating_sub(1), // Prevent underflow
-            },
-            Direction::Down => Position {
-                x: current_head.x,
-                y: current_head.y + 1,
-            },
-            Direction::Left => Position {
-                x: current_head.x.saturating_sub(1), // Prevent underflow
-                y: current_head.y,
+    #[init]
+    fn init(cx: init::Context) -> (Shared, Local) {
+        // Get device peripherals - hardware access
+        let dp = cx.device;
+
+        // Configure system clocks - your chip needs to know how fast to run
+        let rcc = dp.RCC.constrain();
+        // Without this the chip stays on the 16 MHz internal oscillator
+        let clocks = rcc
+            .cfgr
+            .use_hse(HSE_FREQ_MHZ.MHz())
+            .bypass_hse_oscillator()
+            .sysclk(SYSCLK_MHZ.MHz())
+            .freeze();
+
+        // Get GPIO (general purpose IO) ports
+        let gpioa = dp.GPIOA.split();
+
+        // Configure UART pins
+        // PA2 = TX (transmit to computer)
+        // PA3 = RX (receive from computer)
+        let tx_pin = gpioa.pa2.into_alternate();
+        let rx_pin = gpioa.pa3.into_alternate();
+
+        // Create UART interface
+        // USART2 connects to the USB port on your Nucleo board
+        let uart = Serial::new(
+            dp.USART2,
+            (tx_pin, rx_pin),
+            Config::default()
+                .baudrate(BAUD_RATE.bps())
+                .dma(DmaConfig::Tx),
+            &clocks,
+        )
+        .unwrap();
+
+        // Split UART into transmit and receive parts
+        let (tx, mut uart_rx) = uart.split();
+        // Output goes out by DMA (see terminal.rs)
+        let dma1 = StreamsTuple::new(dp.DMA1);
+        let terminal = Terminal::new(tx, dma1.6, &clocks, BAUD_RATE);
+
+        // Receive from now on happens in the usart2_rx task
+        uart_rx.listen();
+
+        // Player two's keyboard, for two-player mode - receive only, on PA10 (USART1 RX)
+        // They watch the same screen as player one
+        let mut player2_rx = Serial::rx(
+            dp.USART1,
+            gpioa.pa10.into_alternate(),
+            Config::default().baudrate(BAUD_RATE.bps()),
+            &clocks,
+        )
+        .unwrap();
+        player2_rx.listen();
+
+        // Optional push buttons (see buttons.rs for the wiring)
+        let gpiob = dp.GPIOB.split();
+        let gpioc = dp.GPIOC.split();
+        let buttons = Buttons::new(gpiob.pb12, gpiob.pb13, gpiob.pb14, gpiob.pb15, gpioc.pc13);
+
+        // Analog joystick (see joystick.rs)
+        #[cfg(feature = "joystick")]
+        let joystick = crate::joystick::Joystick::new(dp.ADC1, gpioa.pa0, gpioa.pa1);
+        #[cfg(not(feature = "joystick"))]
+        let joystick = ();
+
+        // Buzzer for sound effects (see sound.rs)
+        let sound = Sound::new(dp.TIM3, gpiob.pb4, dp.TIM5, &clocks);
+
+        // OLED display on I2C1 (PB8 = SCL, PB9 = SDA)
+        #[cfg(feature = "oled")]
+        let oled = crate::display::Oled::new(I2c::new(
+            dp.I2C1,
+            (gpiob.pb8, gpiob.pb9),
+            400.kHz(),
+            &clocks,
+        ));
+        #[cfg(not(feature = "oled"))]
+        let oled = ();
+
+        // Best score so far, kept in flash across resets
+        let flash = dp.FLASH;
+        let saved_high_score = flash::read_high_score(&flash);
+
+        // Your LED for visual feedback
+        let led = gpioa.pa5.into_push_pull_output();
+
+        // Seeded again when the menu closes
+        let mut game = GameState::new(0, MenuChoice::new().difficulty);
+        game.high_score = saved_high_score;
+
+        // Frame timer - the snake moves one cell per TIM2 tick
+        // TIM2 restarts itself every tick, so frames stay evenly spaced. It counts in
+        // microseconds since a 1 kHz prescaler doesn't fit in 16 bits at 84 MHz.
+        // Ticks wait while the menu is open, so the game starts once it closes.
+        // The tick period is the step interval (game.step_interval_ms(), retimed as the game
+        // speeds up) and drawing happens in the lower priority render task, so a slow frame
+        // can never slow the snake down. There's no step budget to catch up with either - a
+        // tick held up (by the 4 s flash erase, say) is one step late, not a burst of steps.
+        let mut frame_timer = dp.TIM2.counter_us(&clocks);
+        frame_timer.listen(Event::Update);
+        let step_ms = game.step_interval_ms();
+        frame_timer.start((step_ms * 1_000).micros()).unwrap();
+
+        // Keep the debug probe connected while the core sleeps
+        dp.DBGMCU.cr().modify(|_, w| w.dbg_sleep().set_bit());
+
+        // Independent watchdog (runs off its own 32 kHz clock, so a stuck main clock can't stop it)
+        // Once started it can't be turned off, only fed
+        let mut watchdog = IndependentWatchdog::new(dp.IWDG);
+        watchdog.stop_on_debug(&dp.DBGMCU, true); // Don't reset while sitting at a breakpoint
+        watchdog.start(WATCHDOG_TIMEOUT_MS.millis());
+
+        // Startup menu - welcome text and the game options
+        menu::spawn().ok();
+
+        (
+            Shared {
+                game,
+                terminal,
+                sound,
+                uart_input: UartInput::new(next_received_byte),
+                watchdog,
+                menu_open: true, // Spawned above
             },
-            Direction::Right => Position {
-                x: current_head.x + 1,
-                y: current_head.y,
+            Local {
+                uart_rx,
+                player2_rx,
+                frame_timer,
+                buttons,
+                joystick,
+                player2_input: UartInput::new(next_player2_byte),
+                led,
+                flash,
+                saved_high_score,
+                step_ms,
+                // Attract mode, started once the game over screen has been left alone (see demo.rs)
+                demo: Demo::new(DEMO_MOVES),
+                oled,
+                choice: MenuChoice::new(),
             },
-        };
-
-        // Check for collisions BEFORE moving
-        if self.check_collision(new_head) {
-            self.game_over = true;
-            return;
-        }
-
-        // Check if we're eating food
-        let eating_food = new_head == self.food_position;
-
-        if eating_food {
-            // Grow the snake by NOT removing the tail
-            self.score += 10;
-            self.snake_length += 1;
-
-            // Place new food (simple approach - just move it)
-            self.place_new_food();
-        } else {
-            // Move the snake by shifting all segments
-            // Move tail segments forward (from back to front)
-            for i in (1..self.snake_length).rev() {
-                self.snake_body[i] = self.snake_body[i - 1];
-            }
-        }
-
-        // Place new head
-        self.snake_body[0] = new_head;
-
-        // Update the board representation
-        self.update_board();
+        )
     }
 
-    // Check if a position would cause a collision
-    fn check_collision(&self, pos: Position) -> bool {
-        // Check bounds (walls)
-        if pos.x == 0 || pos.x >= BOARD_WIDTH - 1 || pos.y == 0 || pos.y >= BOARD_HEIGHT - 1 {
-            return true;
+    // Runs whenever USART2 has received a byte (RXNE)
+    #[task(binds = USART2, priority = 3, local = [uart_rx])]
+    fn usart2_rx(cx: usart2_rx::Context) {
+        // Reading clears the interrupt, errors (overrun etc.) included
+        if let Ok(byte) = cx.local.uart_rx.read() {
+            cortex_m::interrupt::free(|cs| {
+                queue_received_byte(&mut RX_QUEUE.borrow(cs).borrow_mut(), byte)
+            });
         }
+    }
 
-        // Check self-collision (hitting snake body)
-        for i in 0..self.snake_length {
-            if pos == self.snake_body[i] {
-                return true;
-            }
+    // Runs whenever USART1 (player two) has received a byte
+    #[task(binds = USART1, priority = 3, local = [player2_rx])]
+    fn usart1_rx(cx: usart1_rx::Context) {
+        if let Ok(byte) = cx.local.player2_rx.read() {
+            cortex_m::interrupt::free(|cs| {
+                queue_received_byte(&mut PLAYER2_RX_QUEUE.borrow(cs).borrow_mut(), byte)
+            });
         }
-
-        false
     }
 
-    // Place food in a new location
-    fn place_new_food(&mut self) {
-        // Simple approach: just move food to a fixed location for now
-        // Later we can make this random
-        self.food_position = Position {
-            x: (self.food_position.x + 3) % (BOARD_WIDTH - 2) + 1,
-            y: (self.food_position.y + 2) % (BOARD_HEIGHT - 2) + 1,
-        };
-
-        // Make sure food doesn't spawn on snake (basic check)
-        for i in 0..self.snake_length {
-            if self.food_position == self.snake_body[i] {
-                // Move food one more position if it conflicts
-                self.food_position.x = (self.food_position.x + 1) % (BOARD_WIDTH - 2) + 1;
-                break;
-            }
+    // Nothing to do until the next tick, note change or received key - sleep
+    // Any interrupt wakes the core and its task runs straight away, so input isn't held up
+    #[idle]
+    fn idle(_: idle::Context) -> ! {
+        loop {
+            cortex_m::asm::wfi();
         }
     }
 
-    // NEW: Change direction (with validation)
-    fn change_direction(&mut self, new_direction: Direction) {
-        // Prevent snake from reversing into itself
-        let opposite = match self.snake_direction {
-            Direction::Up => Direction::Down,
-            Direction::Down => Direction::Up,
-            Direction::Left => Direction::Right,
-            Direction::Right => Direction::Left,
-        };
-
-        if new_direction != opposite {
-            self.snake_direction = new_direction;
-        }
+    // The current note is over
+    #[task(binds = TIM5, priority = 2, shared = [sound])]
+    fn sound_tick(mut cx: sound_tick::Context) {
+        cx.shared.sound.lock(|sound| sound.update());
     }
 
-    // NEW: Reset the game
-    fn reset(&mut self) {
-        self.snake_length = 3;
-        self.snake_direction = Direction::Right;
-        self.score = 0;
-        self.game_over = false;
+    // One frame of the game, each time TIM2 ticks
+    #[task(
+        binds = TIM2,
+        priority = 2,
+        shared = [game, terminal, sound, uart_input, menu_open],
+        local = [
+            frame_timer,
+            buttons,
+            joystick,
+            player2_input,
+            led,
+            flash,
+            saved_high_score,
+            step_ms,
+            demo,
+            // Time the countdown has been on its current number
+            countdown_ms: u32 = 0,
+            // Time the game over screen has sat with nobody playing
+            idle_ms: u32 = 0,
+        ]
+    )]
+    fn tick(cx: tick::Context) {
+        let tick::LocalResources {
+            frame_timer,
+            buttons,
+            #[cfg(feature = "joystick")]
+            joystick,
+            player2_input,
+            led,
+            flash,
+            saved_high_score,
+            step_ms,
+            demo,
+            countdown_ms,
+            idle_ms,
+            ..
+        } = cx.local;
+
+        // Clears the update flag, so the next tick runs this again
+        let _ = frame_timer.wait();
+
+        // The menu reads the keys itself, and starts a new game when it closes
+        // The tick still wakes the core each frame, which the menu uses to feed the watchdog
+        let mut menu_open = cx.shared.menu_open;
+        if menu_open.lock(|open| *open) {
+            return;
+        }
 
-        // Reset snake position
-        self.snake_body[0] = Position { x: 10, y: 7 };
-        self.snake_body[1] = Position { x: 9, y: 7 };
-        self.snake_body[2] = Position { x: 8, y: 7 };
+        let mut menu_requested = false;
+
+        (
+            cx.shared.game,
+            cx.shared.terminal,
+            cx.shared.sound,
+            cx.shared.uart_input,
+        )
+            .lock(|game, terminal, sound, uart_input| {
+                // Any key, button or stick push this frame (during the demo it only stops the demo)
+                let mut live_input = false;
+
+                // Handle the keys the receive task queued since the last tick
+                while let Some(input) = uart_input.poll() {
+                    live_input = true;
+
+                    if game.demo_mode {
+                        // Handled below
+                    } else if input == GameInput::Quit
+                        || (input == GameInput::Menu && (game.game_over || game.game_won))
+                    {
+                        // Quit leaves the game for the menu, which starts a fresh one
+                        // The menu reads the rest of the keys itself
+                        menu_requested = true;
+                        break;
+                    } else {
+                        handle_input(input, terminal, game);
+                    }
+                }
+                uart_input.arrow_keys.end_frame();
+                player2_input.arrow_keys.end_frame();
 
-        // Reset food position
-        self.food_position = Position { x: 15, y: 7 };
+                // Visual feedback - LED stays lit for the frame after a key
+                if live_input {
+                    led.set_high();
+                } else {
+                    led.set_low();
+                }
 
-        self.update_board();
-    }
-}
+                // Buttons are sampled once a frame, and steer the snake just like the keys
+                buttons.poll(|button| {
+                    live_input = true;
+                    if !game.demo_mode {
+                        handle_input(button.into(), terminal, game);
+                    }
+                });
+
+                // Joystick is sampled once a frame too, and only steers (it can't restart)
+                #[cfg(feature = "joystick")]
+                if let Some(GameInput::Direction(direction)) = joystick.poll() {
+                    live_input = true;
+                    if !game.demo_mode {
+                        game.enqueue_direction(direction);
+                    }
+                }
 
-// Helper function to send a string over UART
-fn send_string(tx: &mut stm32f4xx_hal::serial::Tx<stm32f4xx_hal::pac::USART2>, text: &[u8]) {
-    for byte in text {
-        block!(tx.write(*byte)).unwrap();
-    }
-}
+                // Player two only steers - restarting, pausing and the menu are player one's
+                while let Some(input) = player2_input.poll() {
+                    live_input = true;
+                    if let GameInput::Direction(direction) = input {
+                        if !game.demo_mode {
+                            game.enqueue_player_direction(1, direction);
+                        }
+                    }
+                }
 
-// Function to send a number as text
-fn send_number(tx: &mut stm32f4xx_hal::serial::Tx<stm32f4xx_hal::pac::USART2>, mut num: u32) {
-    if num == 0 {
-        block!(tx.write(b'0')).unwrap();
-        return;
-    }
+                // Someone's here - stop the demo and give them a real game
+                // Otherwise start the demo once game over has sat long enough
+                if live_input {
+                    *idle_ms = 0;
+                    if game.demo_mode {
+                        game.demo_mode = false;
+                        game.high_score = *saved_high_score; // Demo scores don't count
+                        restart_game(terminal, game);
+                    }
+                } else if (game.game_over || game.game_won) && !game.demo_mode {
+                    *idle_ms += *step_ms;
+                    if *idle_ms >= DEMO_IDLE_MS {
+                        start_demo(demo, terminal, game);
+                    }
+                }
 
-    // Convert number to string (simple approach)
-    let mut digits = [0u8; 10]; // Max 10 digits for u32
-    let mut digit_count = 0;
+                // Count down a new game once a second (move_snake waits until it's done)
+                if game.countdown > 0 {
+                    *countdown_ms += *step_ms;
+                    if *countdown_ms >= 1_000 {
+                        *countdown_ms = 0;
+                        game.countdown -= 1;
+                    }
+                } else {
+                    *countdown_ms = 0;
+                }
 
-    while num > 0 {
-        digits[digit_count] = (num % 10) as u8 + b'0';
-        num /= 10;
-        digit_count += 1;
-    }
+                // The demo steers from its recording, starting over when it runs out or crashes
+                if game.demo_mode && (game.game_over || game.game_won) {
+                    start_demo(demo, terminal, game);
+                }
+                if game.demo_mode && game.countdown == 0 {
+                    match demo.next_direction() {
+                        Some(direction) => game.enqueue_direction(direction),
+                        None => start_demo(demo, terminal, game),
+                    }
+                }
 
-    // Send digits in reverse order (most significant first)
-    for i in (0..digit_count).rev() {
-        block!(tx.write(digits[i])).unwrap();
-    }
-}
+                // Let the computer play (the demo has its own moves)
+                if game.autopilot && !game.demo_mode && game.countdown == 0 {
+                    game.enqueue_direction(game.ai_next_direction());
+                }
 
-// Function to render the game board to terminal
-fn render_game(tx: &mut stm32f4xx_hal::serial::Tx<stm32f4xx_hal::pac::USART2>, game: &GameState) {
-    // Clear screen (ANSI escape code)
-    send_string(tx, b"\x1b[2J\x1b[H");
-
-    // Render the board
-    for row in 0..BOARD_HEIGHT {
-        for col in 0..BOARD_WIDTH {
-            let character = match game.board[row][col] {
-                Cell::Empty => b' ',
-                Cell::Wall => b'#',
-                Cell::Snake => b'o',
-                Cell::Food => b'*',
-            };
-            block!(tx.write(character)).unwrap();
-        }
-        send_string(tx, b"\r\n"); // End of row
-    }
+                // Move the snake forward one step (does nothing while paused)
+                match game.move_snake() {
+                    StepEvent::Ate => sound.beep_eat(),
+                    StepEvent::Died => sound.beep_death(),
+                    _ => {}
+                }
 
-    // Show game info
-    send_string(tx, b"Score: ");
-    send_number(tx, game.score);
-    send_string(tx, b"   Length: ");
-    send_number(tx, game.snake_length as u32);
-    send_string(tx, b"\r\n");
+                // Save a new record (the game updates high_score when it ends)
+                if !game.demo_mode && game.high_score > *saved_high_score {
+                    *saved_high_score = game.high_score;
+                    flash::write_high_score(flash, *saved_high_score);
+                }
 
-    send_string(tx, b"Controls: w/a/s/d to move, r to restart\r\n");
+                // Retime the frames if the score changed the speed (or reset put it back)
+                if game.step_interval_ms() != *step_ms {
+                    *step_ms = game.step_interval_ms();
+                    frame_timer.start((*step_ms * 1_000).micros()).unwrap();
+                }
+            });
 
-    if game.game_over {
-        send_string(tx, b"GAME OVER! Press any key to restart.\r\n");
+        if menu_requested {
+            menu_open.lock(|open| *open = true);
+            menu::spawn().ok();
+        } else {
+            // Already queued means the last frame is still being drawn - skip this one
+            render::spawn().ok();
+        }
     }
-}
 
-#[entry]
-fn main() -> ! {
-    // Get device peripherals - hardware access
-    let dp = pac::Peripherals::take().unwrap();
-
-    // Configure system clocks - your chip needs to know how fast to run
-    let rcc = dp.RCC.constrain();
-    let clocks = rcc.cfgr.freeze();
-
-    // Get GPIO (general purpose IO) ports
-    let gpioa = dp.GPIOA.split();
-
-    // Configure UART pins
-    // PA2 = TX (transmit to computer)
-    // PA3 = RX (receive from computer)
-    let tx_pin = gpioa.pa2.into_alternate();
-    let rx_pin = gpioa.pa3.into_alternate();
-
-    // Create UART interface
-    // USART2 connects to the USB port on your Nucleo board
-    let uart = Serial::new(
-        dp.USART2,
-        (tx_pin, rx_pin),
-        Config::default().baudrate(115200.bps()),
-        &clocks,
-    )
-    .unwrap();
-
-    // Split UART into transmit and receive parts
-    let (mut tx, mut rx) = uart.split();
-
-    // Your LED for visual feedback
-    let mut led = gpioa.pa5.into_push_pull_output();
-
-    let mut game = GameState::new();
-
-    // Welcome message
-    send_string(&mut tx, b"STM32 Snake Game!\r\n");
-    send_string(&mut tx, b"Use w/a/s/d to control the snake.\r\n");
-    send_string(&mut tx, b"Collect food (*) to grow and score points!\r\n");
-    send_string(&mut tx, b"Press any key to start...\r\n");
-
-    // Wait for first keypress to start
-    loop {
-        if rx.read().is_ok() {
-            break;
-        }
+    // Draw the current game state on each display that's built in
+    #[task(
+        priority = 1,
+        shared = [game, terminal, watchdog],
+        local = [oled]
+    )]
+    async fn render(mut cx: render::Context) {
+        #[cfg(feature = "oled")]
+        let oled = cx.local.oled;
+
+        // Draw from a copy, so the tick can carry on while the frame goes out
+        // (at 9600 baud a full redraw takes over a second to send)
+        let game = cx.shared.game.lock(|game| game.clone());
+
+        // Still running - a tick that hangs stops frames and the watchdog resets the chip
+        cx.shared.watchdog.lock(|watchdog| watchdog.feed());
+
+        #[cfg(feature = "oled")]
+        oled.draw(&game);
+
+        cx.shared.terminal.lock(|terminal| {
+            #[cfg(feature = "uart-render")]
+            terminal.draw(&game);
+            #[cfg(feature = "state-packets")]
+            protocol::StatePackets(terminal).draw(&game);
+
+            // Send this frame (and any messages from the tick)
+            // If the line's stalled it stays queued and goes with the next frame
+            let _ = terminal.flush();
+        });
     }
 
-    loop {
-        // Render the current game state
-        render_game(&mut tx, &game);
-
-        // Handle input (non-blocking)
-        for _ in 0..10 {
-            match rx.read() {
-                Ok(received_byte) => {
-                    match received_byte {
-                        b'w' => game.snake_direction = Direction::Up,
-                        b'a' => game.snake_direction = Direction::Left,
-                        b's' => game.snake_direction = Direction::Down,
-                        b'd' => game.snake_direction = Direction::Right,
-                        b'r' => {
-                            game.reset();
-                            send_string(&mut tx, b"Game restarted!\r\n");
-                        }
-                        b'q' => {
-                            send_string(&mut tx, b"Thanks for playing!\r\n");
-                            // In a real game, we might reset or quit
-                        }
-                        _ => {
-                            // Unknown key - ignore
-                        }
+    // The options menu, at startup and from the game over screen
+    // Nothing is locked while it waits for a key, so the core sleeps between them
+    // (woken by the key or the next tick) and the ticks skip the game until it closes
+    #[task(
+        priority = 1,
+        shared = [game, terminal, uart_input, watchdog, menu_open],
+        local = [choice, seed: u32 = 0]
+    )]
+    async fn menu(cx: menu::Context) {
+        let menu::SharedResources {
+            mut game,
+            mut terminal,
+            mut uart_input,
+            mut watchdog,
+            mut menu_open,
+            ..
+        } = cx.shared;
+        let menu::LocalResources { choice, seed, .. } = cx.local;
+
+        let mut menu = Menu::new(*choice);
+
+        loop {
+            // A stalled line just means this drawing is lost, the next key draws it again
+            terminal.lock(|terminal| {
+                let _ = menu.draw(terminal);
+            });
+
+            // Wait for the next whole key (arrow keys come out as w/a/s/d)
+            // The F446 has no hardware RNG, so the seed is stirred with where the frame
+            // timer has got to each time we wake - how long the player takes becomes the
+            // random seed for food placement (unless one is typed in)
+            let key = loop {
+                *seed = seed.wrapping_mul(31).wrapping_add(frame_timer_count());
+                watchdog.lock(|watchdog| watchdog.feed()); // The player can take their time
+
+                let key = uart_input.lock(|uart_input| {
+                    next_received_byte().and_then(|byte| uart_input.arrow_keys.feed(byte))
+                });
+                if let Some(key) = key {
+                    break key;
+                }
+
+                // Sleep with interrupts held off, so a key landing after the check above
+                // still wakes us (WFI wakes on a waiting interrupt even when it can't run yet)
+                cortex_m::interrupt::free(|cs| {
+                    if RX_QUEUE.borrow(cs).borrow().is_empty() {
+                        cortex_m::asm::wfi();
                     }
+                });
+            };
 
-                    // Visual feedback - blink LED when key pressed
-                    led.set_high();
-                    cortex_m::asm::delay(500_000);
-                    led.set_low();
-                }
-                Err(nb::Error::WouldBlock) => {
-                    // No input available - that's fine
-                }
-                Err(_) => {
-                    // Some error occurred
-                }
+            if menu.press(key) {
+                break;
             }
-
-            // Game timing - delay between frames
-            cortex_m::asm::delay(800_000); // ~1 second per frame for now
         }
 
-        // Move the snake forward one step
-        game.move_snake();
+        *choice = menu.choice();
+        (&mut game, &mut terminal, &mut uart_input).lock(|game, terminal, uart_input| {
+            terminal.color_enabled = choice.color_enabled;
+            terminal.unicode_enabled = choice.unicode_enabled;
+            terminal.needs_full_redraw = true;
+            uart_input.shared_keyboard = choice.two_player;
+
+            // A typed seed replays its game, otherwise the wait for keys stirred one up
+            apply_choice(game, choice, choice.seed.unwrap_or(*seed));
+        });
+        menu_open.lock(|open| *open = false);
+
+        render::spawn().ok();
     }
 }
@@ -0,0 +1,246 @@
+// Startup menu over USART2 - pick the game options before playing
+// w/s (or up/down) moves between options, a/d (or left/right) changes one,
+// Enter or space starts the game. On the Seed row the digit keys type a seed.
+
+use crate::terminal::{Terminal, TxStalled};
+use snake_core::{Difficulty, GameConfig, NUM_LAYOUTS};
+
+// Longest start the menu offers - longer is harder, there's less room to turn
+const MAX_START_LENGTH: usize = 12;
+
+// Options picked in the menu
+#[derive(Clone, Copy)]
+pub struct MenuChoice {
+    pub difficulty: Difficulty,
+    pub wrap_enabled: bool,
+    pub shrinking_enabled: bool,
+    pub ghost_enabled: bool,
+    pub two_player: bool,
+    pub color_enabled: bool,
+    pub unicode_enabled: bool,
+    pub start_level: usize,
+    pub start_length: usize,
+    pub seed: Option<u32>, // Typed in to replay a game's food, None picks one at random
+}
+
+impl MenuChoice {
+    // What the menu starts out showing
+    pub fn new() -> Self {
+        MenuChoice {
+            difficulty: Difficulty::Medium,
+            wrap_enabled: false,
+            shrinking_enabled: false,
+            ghost_enabled: false,
+            two_player: false,
+            color_enabled: true,
+            unicode_enabled: false,
+            start_level: 1,
+            start_length: GameConfig::default().initial_length,
+            seed: None,
+        }
+    }
+}
+
+// Rows of the menu, top to bottom
+#[derive(Clone, Copy, PartialEq)]
+enum MenuItem {
+    Speed,
+    Wrap,
+    Shrinking,
+    Ghost,
+    Players,
+    Color,
+    Unicode,
+    StartLevel,
+    StartLength,
+    Seed,
+}
+
+const MENU_ITEMS: [MenuItem; 10] = [
+    MenuItem::Speed,
+    MenuItem::Wrap,
+    MenuItem::Shrinking,
+    MenuItem::Ghost,
+    MenuItem::Players,
+    MenuItem::Color,
+    MenuItem::Unicode,
+    MenuItem::StartLevel,
+    MenuItem::StartLength,
+    MenuItem::Seed,
+];
+
+// The menu while it's open - the options so far and the row the cursor is on
+// The menu task in main.rs draws it, waits (asleep) for a key and hands it to press()
+pub struct Menu {
+    choice: MenuChoice,
+    selected: usize,
+}
+
+impl Menu {
+    // Open the menu showing the options from last time
+    pub fn new(choice: MenuChoice) -> Self {
+        Menu {
+            choice,
+            selected: 0,
+        }
+    }
+
+    pub fn choice(&self) -> MenuChoice {
+        self.choice
+    }
+
+    pub fn draw(&self, terminal: &mut Terminal) -> Result<(), TxStalled> {
+        draw_menu(terminal, &self.choice, self.selected)
+    }
+
+    // Act on one whole key (arrow keys come out as w/a/s/d), returning true to start the game
+    pub fn press(&mut self, key: u8) -> bool {
+        let choice = &mut self.choice;
+        let selected = &mut self.selected;
+
+        match key {
+            b'w' => *selected = (*selected + MENU_ITEMS.len() - 1) % MENU_ITEMS.len(),
+            b's' => *selected = (*selected + 1) % MENU_ITEMS.len(),
+            b'a' => change_option(choice, MENU_ITEMS[*selected], false),
+            b'd' => change_option(choice, MENU_ITEMS[*selected], true),
+            b'\r' | b'\n' | b' ' => return true,
+            b'0'..=b'9' if MENU_ITEMS[*selected] == MenuItem::Seed => {
+                // Digits that would overflow are ignored
+                let digit = (key - b'0') as u32;
+                let typed = choice.seed.unwrap_or(0);
+                if let Some(seed) = typed.checked_mul(10).and_then(|s| s.checked_add(digit)) {
+                    choice.seed = Some(seed);
+                }
+            }
+            // Backspace (terminals send either) - the last digit goes, and with none left it's random again
+            0x08 | 0x7f if MENU_ITEMS[*selected] == MenuItem::Seed => {
+                choice.seed = choice.seed.map(|seed| seed / 10).filter(|&seed| seed > 0);
+            }
+            _ => {}
+        }
+
+        false
+    }
+}
+
+// Step one option to its next (or previous) value, wrapping around
+fn change_option(choice: &mut MenuChoice, item: MenuItem, forward: bool) {
+    match item {
+        MenuItem::Speed => {
+            choice.difficulty = match (choice.difficulty, forward) {
+                (Difficulty::Slow, true) | (Difficulty::Fast, false) => Difficulty::Medium,
+                (Difficulty::Medium, true) | (Difficulty::Slow, false) => Difficulty::Fast,
+                (Difficulty::Fast, true) | (Difficulty::Medium, false) => Difficulty::Slow,
+            }
+        }
+        MenuItem::Wrap => choice.wrap_enabled = !choice.wrap_enabled,
+        MenuItem::Shrinking => choice.shrinking_enabled = !choice.shrinking_enabled,
+        MenuItem::Ghost => choice.ghost_enabled = !choice.ghost_enabled,
+        MenuItem::Players => choice.two_player = !choice.two_player,
+        MenuItem::Color => choice.color_enabled = !choice.color_enabled,
+        MenuItem::Unicode => choice.unicode_enabled = !choice.unicode_enabled,
+        MenuItem::Seed => choice.seed = None, // Typed with the digit keys, a/d go back to random
+        MenuItem::StartLevel => {
+            choice.start_level = if forward {
+                choice.start_level % NUM_LAYOUTS + 1
+            } else {
+                (choice.start_level + NUM_LAYOUTS - 2) % NUM_LAYOUTS + 1
+            }
+        }
+        MenuItem::StartLength => {
+            // From the usual length up to MAX_START_LENGTH
+            let shortest = GameConfig::default().initial_length;
+            choice.start_length = match (choice.start_length, forward) {
+                (MAX_START_LENGTH, true) => shortest,
+                (length, true) => length + 1,
+                (length, false) if length <= shortest => MAX_START_LENGTH,
+                (length, false) => length - 1,
+            }
+        }
+    }
+}
+
+fn on_off(enabled: bool) -> &'static [u8] {
+    if enabled {
+        b"on"
+    } else {
+        b"off"
+    }
+}
+
+// Draw the whole menu, with the selected row marked (and inverted if colors are on)
+fn draw_menu(
+    terminal: &mut Terminal,
+    choice: &MenuChoice,
+    selected: usize,
+) -> Result<(), TxStalled> {
+    terminal.send_string(b"\x1b[2J\x1b[H")?;
+    terminal.send_string(b"STM32 Snake Game!\r\n")?;
+    terminal.send_string(b"Collect food (*) to grow and score points!\r\n")?;
+    terminal.send_string(b"Grab the bonus ($) for 50 points before it vanishes.\r\n")?;
+    terminal.send_string(b"Avoid poison (X) - it costs points and shrinks you.\r\n")?;
+    terminal.send_string(b"Portals (@) come in pairs - go in one, come out the other.\r\n\r\n")?;
+
+    for (i, item) in MENU_ITEMS.iter().enumerate() {
+        let highlight = i == selected && choice.color_enabled;
+
+        terminal.send_string(if i == selected { b"> " } else { b"  " })?;
+        if highlight {
+            terminal.send_string(b"\x1b[7m")?; // Reverse video
+        }
+
+        match item {
+            MenuItem::Speed => {
+                terminal.send_string(b"Speed:       ")?;
+                terminal.send_string(choice.difficulty.name())?;
+            }
+            MenuItem::Wrap => {
+                terminal.send_string(b"Wrap walls:  ")?;
+                terminal.send_string(on_off(choice.wrap_enabled))?;
+            }
+            MenuItem::Shrinking => {
+                terminal.send_string(b"Closing in:  ")?; // The walls move in as you eat
+                terminal.send_string(on_off(choice.shrinking_enabled))?;
+            }
+            MenuItem::Ghost => {
+                terminal.send_string(b"Ghost mode:  ")?; // Practice - the snake can cross itself
+                terminal.send_string(on_off(choice.ghost_enabled))?;
+            }
+            MenuItem::Players => {
+                terminal.send_string(b"Players:     ")?; // Player two uses the arrow keys, or USART1
+                terminal.send_string(if choice.two_player { b"2" } else { b"1" })?;
+            }
+            MenuItem::Color => {
+                terminal.send_string(b"Colors:      ")?;
+                terminal.send_string(on_off(choice.color_enabled))?;
+            }
+            MenuItem::Unicode => {
+                terminal.send_string(b"Box walls:   ")?; // Needs a UTF-8 terminal
+                terminal.send_string(on_off(choice.unicode_enabled))?;
+            }
+            MenuItem::StartLevel => {
+                terminal.send_string(b"Start level: ")?;
+                terminal.send_number(choice.start_level as u32)?;
+            }
+            MenuItem::StartLength => {
+                terminal.send_string(b"Length:      ")?;
+                terminal.send_number(choice.start_length as u32)?;
+            }
+            MenuItem::Seed => {
+                terminal.send_string(b"Seed:        ")?;
+                match choice.seed {
+                    Some(seed) => terminal.send_number(seed)?,
+                    None => terminal.send_string(b"random (type digits to pick one)")?,
+                }
+            }
+        }
+
+        if highlight {
+            terminal.send_string(b"\x1b[0m")?;
+        }
+        terminal.send_string(b"\r\n")?;
+    }
+
+    terminal.send_string(b"\r\nw/s to choose, a/d to change, Enter or space to start\r\n")?;
+    terminal.flush()
+}
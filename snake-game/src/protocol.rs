@@ -0,0 +1,95 @@
+// Machine-readable game state over USART2, for a desktop front-end
+// One packet is sent per frame:
+//
+//   0xAA  len (u16, little endian)  payload (len bytes)  checksum (XOR of the payload)
+//
+// Payload:
+//   board width (u8), board height (u8)
+//   score (u32, little endian)
+//   flags (u8) - bit 0 game over, bit 1 won, bit 2 paused
+//   snake length (u8), then x, y (u8 each) per segment, head first
+//   food count (u8), then x, y (u8 each) per food
+//
+// Text messages (menu, "Game restarted!") still go out on the same port, so a
+// host should look for 0xAA and drop any packet whose checksum doesn't match.
+
+use crate::renderer::Renderer;
+use crate::terminal::{Terminal, TxStalled};
+use snake_core::{GameState, Position, BOARD_HEIGHT, BOARD_WIDTH};
+
+const START_BYTE: u8 = 0xAA;
+
+const FLAG_GAME_OVER: u8 = 1 << 0;
+const FLAG_WON: u8 = 1 << 1;
+const FLAG_PAUSED: u8 = 1 << 2;
+
+// Draws by sending packets out through the terminal's UART
+pub struct StatePackets<'a>(pub &'a mut Terminal);
+
+impl Renderer for StatePackets<'_> {
+    fn draw(&mut self, game: &GameState) {
+        // A packet cut short by a stalled line fails its checksum, the next one is whole
+        let _ = send_state_packet(self.0, game);
+    }
+}
+
+// Sends payload bytes and keeps the running checksum
+struct PacketWriter<'a> {
+    terminal: &'a mut Terminal,
+    checksum: u8,
+}
+
+impl PacketWriter<'_> {
+    fn send(&mut self, bytes: &[u8]) -> Result<(), TxStalled> {
+        for byte in bytes {
+            self.checksum ^= byte;
+        }
+        self.terminal.send_string(bytes)
+    }
+
+    fn send_positions<'p>(
+        &mut self,
+        positions: impl ExactSizeIterator<Item = &'p Position>,
+    ) -> Result<(), TxStalled> {
+        self.send(&[positions.len() as u8])?;
+        for pos in positions {
+            self.send(&[pos.x as u8, pos.y as u8])?;
+        }
+        Ok(())
+    }
+}
+
+pub fn send_state_packet(terminal: &mut Terminal, game: &GameState) -> Result<(), TxStalled> {
+    let snake = game.snake_segments();
+    let food = game.food();
+
+    // Fixed part (size, score, flags and the two counts) plus two bytes per position
+    let len = 2 + 4 + 1 + 1 + 2 * snake.len() + 1 + 2 * food.len();
+
+    let mut flags = 0;
+    if game.game_over {
+        flags |= FLAG_GAME_OVER;
+    }
+    if game.game_won {
+        flags |= FLAG_WON;
+    }
+    if game.paused {
+        flags |= FLAG_PAUSED;
+    }
+
+    terminal.send_string(&[START_BYTE])?;
+    terminal.send_string(&(len as u16).to_le_bytes())?;
+
+    let mut packet = PacketWriter {
+        terminal,
+        checksum: 0,
+    };
+    packet.send(&[BOARD_WIDTH as u8, BOARD_HEIGHT as u8])?;
+    packet.send(&game.score.to_le_bytes())?;
+    packet.send(&[flags])?;
+    packet.send_positions(snake)?;
+    packet.send_positions(food.iter())?;
+
+    let checksum = packet.checksum;
+    terminal.send_string(&[checksum])
+}
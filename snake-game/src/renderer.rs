@@ -0,0 +1,20 @@
+// Something that can show the game - the serial terminal, the OLED, or
+// anything else that gets added later
+// draw is called once per frame with the state after the last step
+
+use snake_core::GameState;
+
+pub trait Renderer {
+    fn draw(&mut self, game: &GameState);
+}
+
+// What to show over the middle of the board while a new game counts down
+pub fn countdown_label(game: &GameState) -> Option<&'static str> {
+    match game.countdown {
+        0 => None,
+        1 => Some("GO!"),
+        2 => Some("1"),
+        3 => Some("2"),
+        _ => Some("3"),
+    }
+}
@@ -0,0 +1,101 @@
+// Piezo buzzer sound effects on PB4 (TIM3 channel 1 PWM)
+//
+// A tune is a list of (frequency in Hz, length in ms) notes. Playing one only
+// starts the first note - the TIM5 interrupt calls update() to move on to the
+// next, so sounds never hold up the game. Without a buzzer wired up PB4 just
+// toggles on its own, so boards without one work the same.
+
+use stm32f4xx_hal::{
+    gpio::{Debugger, PB4},
+    pac::{TIM3, TIM5},
+    prelude::*,
+    rcc::Clocks,
+    timer::{CounterUs, Event, PwmChannel, PwmHzManager},
+};
+
+// Set to false to keep the game quiet
+const SOUND_ENABLED: bool = true;
+
+// Short high beep
+const EAT_TUNE: &[(u32, u32)] = &[(2_000, 40)];
+// Falling tones
+const DEATH_TUNE: &[(u32, u32)] = &[(880, 150), (660, 150), (440, 150), (220, 300)];
+
+pub struct Sound {
+    pwm: PwmHzManager<TIM3>,
+    buzzer: PwmChannel<TIM3, 0>,
+    note_timer: CounterUs<TIM5>, // Ends the current note
+    tune: &'static [(u32, u32)],
+    next_note: usize, // Index into `tune` of the note after this one
+    playing: bool,
+}
+
+impl Sound {
+    // PB4 comes out of reset as a JTAG pin, but the probe only uses SWD so it's free
+    pub fn new(tim3: TIM3, buzzer_pin: PB4<Debugger>, tim5: TIM5, clocks: &Clocks) -> Self {
+        let (pwm, (ch1, ..)) = tim3.pwm_hz(1.kHz(), clocks);
+
+        // The end of a note runs the sound_tick task (see main.rs)
+        let mut note_timer = tim5.counter_us(clocks);
+        note_timer.listen(Event::Update);
+
+        Sound {
+            pwm,
+            buzzer: ch1.with(buzzer_pin.into_alternate()),
+            note_timer,
+            tune: &[],
+            next_note: 0,
+            playing: false,
+        }
+    }
+
+    // Food or bonus eaten
+    pub fn beep_eat(&mut self) {
+        self.play(EAT_TUNE);
+    }
+
+    // Game over
+    pub fn beep_death(&mut self) {
+        self.play(DEATH_TUNE);
+    }
+
+    // Called from the TIM5 interrupt when a note is over
+    pub fn update(&mut self) {
+        // wait() clears the flag, which must happen every time or the interrupt keeps firing
+        if self.note_timer.wait().is_ok() && self.playing {
+            self.start_next_note();
+        }
+    }
+
+    // Start a tune from its first note, cutting off whatever was playing
+    fn play(&mut self, tune: &'static [(u32, u32)]) {
+        if !SOUND_ENABLED {
+            return;
+        }
+
+        self.tune = tune;
+        self.next_note = 0;
+        self.start_next_note();
+    }
+
+    fn start_next_note(&mut self) {
+        match self.tune.get(self.next_note) {
+            Some(&(frequency, length_ms)) => {
+                // Square wave at the note's pitch (50% duty is loudest for a piezo)
+                self.pwm.set_period(frequency.Hz());
+                self.buzzer.set_duty(self.buzzer.get_max_duty() / 2);
+                self.buzzer.enable();
+
+                self.note_timer.start((length_ms * 1_000).micros()).unwrap();
+                self.next_note += 1;
+                self.playing = true;
+            }
+            None => {
+                // End of the tune
+                self.buzzer.disable();
+                let _ = self.note_timer.cancel();
+                self.playing = false;
+            }
+        }
+    }
+}
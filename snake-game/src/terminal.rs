@@ -0,0 +1,538 @@
+// Terminal output over USART2 - text messages, and a renderer that draws the
+// board and status with ANSI escapes. The board is only drawn here when the
+// "uart-render" feature (on by default) is enabled.
+//
+// Nothing is sent a byte at a time - text goes into one of two buffers, and
+// flush hands the full one to DMA (DMA1 stream 6) while the other fills up.
+// The CPU only waits if a buffer fills before the last one finished sending,
+// and then only for so long - a stalled transfer makes the send fail instead,
+// and the renderer drops the rest of the frame and tries again next time.
+
+use crate::renderer::{countdown_label, Renderer};
+use embedded_dma::ReadBuffer;
+use heapless::Vec;
+use snake_core::{Cell, Difficulty, Direction, GameState, BOARD_HEIGHT, BOARD_WIDTH};
+use stm32f4xx_hal::{
+    dma::{config::DmaConfig, traits::StreamISR, MemoryToPeripheral, Stream6, Transfer},
+    pac::{DMA1, USART2},
+    rcc::Clocks,
+    serial::Tx,
+};
+
+// Bytes per transmit buffer - a diff frame fits easily, a full redraw takes a few
+const TX_BUFFER_SIZE: usize = 1024;
+
+// Bits on the wire per byte sent - a start bit, 8 data bits and a stop bit
+const BITS_PER_BYTE: u32 = 10;
+
+// The last transfer didn't finish in time - what didn't fit was dropped
+#[derive(Debug)]
+pub struct TxStalled;
+
+// One transmit buffer, DMA sends whatever has been pushed into it
+pub struct TxBuffer(&'static mut Vec<u8, TX_BUFFER_SIZE>);
+
+// SAFETY: the Vec lives forever and isn't changed while the Transfer owns it
+unsafe impl ReadBuffer for TxBuffer {
+    type Word = u8;
+
+    unsafe fn read_buffer(&self) -> (*const u8, usize) {
+        (self.0.as_ptr(), self.0.len())
+    }
+}
+
+// USART2 TX is channel 4 of DMA1 stream 6
+type TxTransfer = Transfer<Stream6<DMA1>, 4, Tx<USART2>, MemoryToPeripheral, TxBuffer>;
+
+// ANSI color codes (SGR parameters)
+const COLOR_RESET: u32 = 0;
+const COLOR_RED: u32 = 31;
+const COLOR_GREEN: u32 = 32;
+const COLOR_YELLOW: u32 = 33;
+const COLOR_BLUE: u32 = 34;
+const COLOR_MAGENTA: u32 = 35;
+const COLOR_CYAN: u32 = 36;
+const COLOR_GRAY: u32 = 90;
+const COLOR_BRIGHT_GREEN: u32 = 92;
+const COLOR_BRIGHT_YELLOW: u32 = 93;
+
+// Lines under the board: status, two lines of controls, then the banner
+const STATUS_LINES: usize = 4;
+
+// Game over summary under the banner: a gap, the stats, the graph title,
+// then the graph and its axis
+const GRAPH_ROWS: u32 = 5;
+const SUMMARY_LINES: usize = 3 + GRAPH_ROWS as usize + 1;
+
+// Everything the status block shows: level, score, player two's score (two-player games),
+// high score, combo, length, time, speed, options (wrap, ghost, autopilot, assist), banner
+// (the options are grouped since tuples only compare up to 12 things)
+type Status = (
+    usize,
+    u32,
+    Option<u32>,
+    u32,
+    u32,
+    usize,
+    u32,
+    Difficulty,
+    (bool, bool, bool, bool),
+    &'static [u8],
+);
+
+// The serial terminal, plus what it's currently showing so frames only redraw what changed
+pub struct Terminal {
+    transfer: TxTransfer,      // Owns the UART and the buffer being sent
+    filling: Option<TxBuffer>, // Buffer being written to (only None inside flush)
+    sending: bool,             // A transfer has been started (it may have finished since)
+    wait_retries: u32,         // How many 1 ms retries before the last transfer counts as stalled
+    retry_cycles: u32,         // CPU cycles in 1 ms
+    shown: [[(&'static [u8], u32); BOARD_WIDTH]; BOARD_HEIGHT], // Character and color of each cell
+    shown_status: Option<Status>, // Status block as last drawn
+    pub needs_full_redraw: bool, // Screen is blank or out of date (first frame, restart)
+    pub color_enabled: bool,   // Off for terminals that don't understand ANSI colors
+    pub unicode_enabled: bool, // Box-drawing walls, for terminals that understand UTF-8
+}
+
+impl Terminal {
+    // `tx` needs DMA turned on in its serial config, and `baud_rate` is the one it was set up with
+    pub fn new(tx: Tx<USART2>, stream: Stream6<DMA1>, clocks: &Clocks, baud_rate: u32) -> Self {
+        let first = cortex_m::singleton!(: Vec<u8, TX_BUFFER_SIZE> = Vec::new()).unwrap();
+        let second = cortex_m::singleton!(: Vec<u8, TX_BUFFER_SIZE> = Vec::new()).unwrap();
+
+        // Set up but not started - the first flush starts it
+        let transfer = Transfer::init_memory_to_peripheral(
+            stream,
+            tx,
+            TxBuffer(first),
+            None,
+            DmaConfig::default().memory_increment(true),
+        );
+
+        // A full buffer gets half as long again as it should take to send before giving up
+        // (about 1.6 s at 9600 baud, 130 ms at 115200)
+        let full_buffer_ms = TX_BUFFER_SIZE as u32 * BITS_PER_BYTE * 1_000 / baud_rate;
+
+        Terminal {
+            transfer,
+            filling: Some(TxBuffer(second)),
+            sending: false,
+            wait_retries: full_buffer_ms * 3 / 2 + 1,
+            retry_cycles: clocks.sysclk().raw() / 1_000,
+            shown: [[(b" ", COLOR_RESET); BOARD_WIDTH]; BOARD_HEIGHT],
+            shown_status: None,
+            needs_full_redraw: true,
+            color_enabled: true,
+            unicode_enabled: false,
+        }
+    }
+
+    // Queue one byte, sending the buffer first if it's full
+    fn send_byte(&mut self, byte: u8) -> Result<(), TxStalled> {
+        if let Some(buf) = self.filling.as_mut() {
+            if buf.0.push(byte).is_ok() {
+                return Ok(());
+            }
+        }
+
+        self.flush()?;
+        if let Some(buf) = self.filling.as_mut() {
+            let _ = buf.0.push(byte); // Just emptied, so there's room
+        }
+        Ok(())
+    }
+
+    // Send a string over UART
+    // Stops at the first byte that can't be queued, the rest of the string is dropped
+    pub fn send_string(&mut self, text: &[u8]) -> Result<(), TxStalled> {
+        for byte in text {
+            self.send_byte(*byte)?;
+        }
+        Ok(())
+    }
+
+    // Is the last flush still going out?
+    fn is_sending(&self) -> bool {
+        self.sending && !self.transfer.is_transfer_complete()
+    }
+
+    // Start sending everything queued so far (call once a frame, or after a whole message)
+    // If the last transfer is stuck, what's queued stays queued for the next try
+    pub fn flush(&mut self) -> Result<(), TxStalled> {
+        let Some(buf) = self.filling.take() else {
+            return Ok(());
+        };
+        if buf.0.is_empty() {
+            self.filling = Some(buf);
+            return Ok(());
+        }
+
+        // Wait (a bounded time) for the last transfer before swapping it out
+        if let Err(stalled) = self.wait_for_send() {
+            self.filling = Some(buf);
+            return Err(stalled);
+        }
+
+        // Without hardware double buffering next_transfer always succeeds
+        if let Ok((sent, _)) = self.transfer.next_transfer(buf) {
+            sent.0.clear();
+            self.filling = Some(sent);
+        }
+        self.sending = true;
+        Ok(())
+    }
+
+    // Give the last transfer up to wait_retries ms to finish
+    fn wait_for_send(&self) -> Result<(), TxStalled> {
+        for _ in 0..self.wait_retries {
+            if !self.is_sending() {
+                return Ok(());
+            }
+            cortex_m::asm::delay(self.retry_cycles);
+        }
+
+        if self.is_sending() {
+            Err(TxStalled)
+        } else {
+            Ok(())
+        }
+    }
+
+    // Send a number as text
+    pub fn send_number(&mut self, mut num: u32) -> Result<(), TxStalled> {
+        if num == 0 {
+            return self.send_byte(b'0');
+        }
+
+        // Convert number to string (simple approach)
+        let mut digits = [0u8; 10]; // Max 10 digits for u32
+        let mut digit_count = 0;
+
+        while num > 0 {
+            digits[digit_count] = (num % 10) as u8 + b'0';
+            num /= 10;
+            digit_count += 1;
+        }
+
+        // Send digits in reverse order (most significant first)
+        for i in (0..digit_count).rev() {
+            self.send_byte(digits[i])?;
+        }
+        Ok(())
+    }
+
+    // Send a time as MM:SS (minutes keep counting past 99)
+    fn send_time(&mut self, secs: u32) -> Result<(), TxStalled> {
+        let minutes = secs / 60;
+        let seconds = secs % 60;
+
+        if minutes < 10 {
+            self.send_byte(b'0')?;
+        }
+        self.send_number(minutes)?;
+        self.send_byte(b':')?;
+        self.send_byte(b'0' + (seconds / 10) as u8)?;
+        self.send_byte(b'0' + (seconds % 10) as u8)
+    }
+
+    // Switch the terminal text color
+    fn send_color(&mut self, code: u32) -> Result<(), TxStalled> {
+        self.send_string(b"\x1b[")?;
+        self.send_number(code)?;
+        self.send_byte(b'm')
+    }
+
+    // Move the terminal cursor (0-based row/col, ANSI counts from 1)
+    fn move_cursor(&mut self, row: usize, col: usize) -> Result<(), TxStalled> {
+        self.send_string(b"\x1b[")?;
+        self.send_number(row as u32 + 1)?;
+        self.send_byte(b';')?;
+        self.send_number(col as u32 + 1)?;
+        self.send_byte(b'H')
+    }
+}
+
+// Character (one column wide, but maybe several UTF-8 bytes) and color for one board cell
+fn cell_glyph(game: &GameState, row: usize, col: usize, unicode: bool) -> (&'static [u8], u32) {
+    let on_border = row == 0 || row == BOARD_HEIGHT - 1 || col == 0 || col == BOARD_WIDTH - 1;
+
+    match game.board[row][col] {
+        Cell::Empty => (b" ", COLOR_RESET),
+        // In wrap mode the border is a portal, so draw it faintly
+        Cell::Wall if on_border && game.wrap_enabled => (b".", COLOR_GRAY),
+        Cell::Wall if on_border && unicode => (border_glyph(row, col), COLOR_BLUE),
+        Cell::Wall if unicode => ("█".as_bytes(), COLOR_BLUE),
+        Cell::Wall => (b"#", COLOR_BLUE),
+        Cell::Snake => (b"o", COLOR_GREEN),
+        Cell::SnakeHead => (head_glyph(game.player_direction(0)), COLOR_BRIGHT_GREEN),
+        // Player two
+        Cell::Snake2 => (b"=", COLOR_YELLOW),
+        Cell::Snake2Head => (head_glyph(game.player_direction(1)), COLOR_BRIGHT_YELLOW),
+        Cell::Food => (b"*", COLOR_RED),
+        // Blinks off every other step once it's about to go
+        Cell::Bonus if game.bonus_expiring() && game.ticks_elapsed % 2 == 1 => (b" ", COLOR_RESET),
+        Cell::Bonus => (b"$", COLOR_YELLOW),
+        Cell::Poison => (b"X", COLOR_MAGENTA),
+        Cell::Portal => (b"@", COLOR_CYAN),
+    }
+}
+
+// Heads point the way the snake is going
+fn head_glyph(direction: Direction) -> &'static [u8] {
+    match direction {
+        Direction::Up => b"^",
+        Direction::Down => b"v",
+        Direction::Left => b"<",
+        Direction::Right => b">",
+    }
+}
+
+// Box-drawing piece for a border cell: corners, then the edges between them
+fn border_glyph(row: usize, col: usize) -> &'static [u8] {
+    let glyph = match (row, col) {
+        (0, 0) => "┌",
+        (0, c) if c == BOARD_WIDTH - 1 => "┐",
+        (r, 0) if r == BOARD_HEIGHT - 1 => "└",
+        (r, c) if r == BOARD_HEIGHT - 1 && c == BOARD_WIDTH - 1 => "┘",
+        (0, _) => "─",
+        (r, _) if r == BOARD_HEIGHT - 1 => "─",
+        _ => "│",
+    };
+    glyph.as_bytes()
+}
+
+// Draws the game board and status in the terminal
+impl Renderer for Terminal {
+    fn draw(&mut self, game: &GameState) {
+        // Line stalled part way through - what's on screen is anyone's guess now,
+        // so drop the rest of the frame and redraw everything once it's moving again
+        if self.draw_frame(game).is_err() {
+            self.needs_full_redraw = true;
+        }
+    }
+}
+
+impl Terminal {
+    // Only cells that changed since the last frame are sent, each behind a cursor move
+    fn draw_frame(&mut self, game: &GameState) -> Result<(), TxStalled> {
+        // Skip this frame if the last one is still going out - only changes are
+        // sent, so the next frame catches up
+        if self.is_sending() {
+            return Ok(());
+        }
+
+        let full_redraw = self.needs_full_redraw;
+        self.needs_full_redraw = false;
+
+        if full_redraw {
+            // Clear screen (ANSI escape code)
+            self.send_string(b"\x1b[2J\x1b[H")?;
+        }
+
+        // Render the board
+        // Colors only get sent when they change, so runs of the same cell stay cheap
+        let mut current_color = COLOR_RESET;
+        // Where the next character will land (after a diff frame it's below the status)
+        let mut cursor = if full_redraw { Some((0, 0)) } else { None };
+
+        for row in 0..BOARD_HEIGHT {
+            for col in 0..BOARD_WIDTH {
+                let (character, color) = cell_glyph(game, row, col, self.unicode_enabled);
+
+                if !full_redraw && self.shown[row][col] == (character, color) {
+                    continue; // Already on screen
+                }
+
+                if cursor != Some((row, col)) {
+                    self.move_cursor(row, col)?;
+                }
+
+                // Color doesn't matter for a space
+                if self.color_enabled && character != b" " && color != current_color {
+                    self.send_color(color)?;
+                    current_color = color;
+                }
+                self.send_string(character)?;
+
+                self.shown[row][col] = (character, color);
+                cursor = Some((row, col + 1));
+            }
+        }
+
+        // Countdown over the middle of the board - `shown` is updated too, so the
+        // cells underneath get redrawn once it's gone
+        if let Some(label) = countdown_label(game) {
+            let row = BOARD_HEIGHT / 2;
+            let col = (BOARD_WIDTH - label.len()) / 2;
+            self.move_cursor(row, col)?;
+
+            if self.color_enabled && current_color != COLOR_YELLOW {
+                self.send_color(COLOR_YELLOW)?;
+                current_color = COLOR_YELLOW;
+            }
+            for i in 0..label.len() {
+                let character = &label.as_bytes()[i..i + 1];
+                self.send_string(character)?;
+                self.shown[row][col + i] = (character, COLOR_YELLOW);
+            }
+        }
+
+        // Back to normal so the status text (and terminal prompt) isn't tinted
+        if self.color_enabled && current_color != COLOR_RESET {
+            self.send_color(COLOR_RESET)?;
+        }
+
+        // Show game info below the board, only when something in it changed
+        // A one-player game that's over gets the summary and score graph under that
+        let show_summary =
+            (game.game_over || game.game_won) && !game.demo_mode && game.players() == 1;
+        let banner: &'static [u8] = if game.demo_mode {
+            b"DEMO - press any key to play."
+        } else if game.game_won {
+            b"YOU WIN! Press any key to restart, m for the menu."
+        } else if game.game_over && game.players() == 2 {
+            match game.winner() {
+                Some(0) => b"PLAYER 1 WINS! Press any key to restart, m for the menu.",
+                Some(_) => b"PLAYER 2 WINS! Press any key to restart, m for the menu.",
+                None => b"DRAW! Press any key to restart, m for the menu.",
+            }
+        } else if game.game_over && game.can_undo() {
+            b"GAME OVER! Press u to undo, any other key to restart, m for the menu."
+        } else if game.game_over {
+            b"GAME OVER! Press any key to restart, m for the menu."
+        } else if game.paused {
+            b"PAUSED - press p or space to resume."
+        } else {
+            b""
+        };
+        let status = (
+            game.level,
+            game.score,
+            (game.players() == 2).then_some(game.player2_score),
+            game.high_score,
+            game.combo,
+            game.snake_length(),
+            game.elapsed_secs(),
+            game.difficulty,
+            (
+                game.wrap_enabled,
+                game.allow_self_overlap,
+                game.autopilot,
+                game.assist,
+            ),
+            banner,
+        );
+
+        if full_redraw || self.shown_status != Some(status) {
+            self.shown_status = Some(status);
+
+            // Each line clears whatever was left on it (ESC [K)
+            self.move_cursor(BOARD_HEIGHT, 0)?;
+            self.send_string(b"Level: ")?;
+            self.send_number(game.level as u32)?;
+            if game.players() == 2 {
+                self.send_string(b"   P1: ")?;
+                self.send_number(game.score)?;
+                self.send_string(b"   P2: ")?;
+                self.send_number(game.player2_score)?;
+            } else {
+                self.send_string(b"   Score: ")?;
+                self.send_number(game.score)?;
+            }
+            self.send_string(b"   High: ")?;
+            self.send_number(game.high_score)?;
+            if game.combo > 1 {
+                self.send_string(b"   Combo: x")?;
+                self.send_number(game.combo)?;
+            }
+            self.send_string(b"   Length: ")?;
+            self.send_number(game.snake_length() as u32)?;
+            self.send_string(b"   Time: ")?;
+            self.send_time(game.elapsed_secs())?;
+            self.send_string(b"   Speed: ")?;
+            self.send_string(game.difficulty.name())?;
+            if game.wrap_enabled {
+                self.send_string(b"   Wrap: on")?;
+            }
+            if game.allow_self_overlap {
+                self.send_string(b"   GHOST (practice)")?;
+            }
+            if game.autopilot {
+                self.send_string(b"   Autopilot")?;
+            }
+            if game.assist {
+                self.send_string(b"   Assist")?;
+            }
+            self.send_string(b"\x1b[K\r\n")?;
+
+            self.send_string(
+                b"Controls: w/a/s/d to move, p/space to pause, r to restart, u to undo, q to quit to the menu\x1b[K\r\n",
+            )?;
+            self.send_string(
+                b"Options: t to toggle wrap, l to change starting level, c for autopilot, h for assist\x1b[K\r\n",
+            )?;
+
+            self.send_string(banner)?;
+            // So a good game's food can be replayed (type the seed into the menu)
+            if (game.game_over || game.game_won) && !game.demo_mode {
+                self.send_string(b" Seed: ")?;
+                self.send_number(game.seed())?;
+            }
+            if show_summary {
+                self.draw_summary(game)?;
+            }
+
+            // Clear the rest of the screen (old banner or messages)
+            self.send_string(b"\x1b[J")?;
+        }
+
+        // Park the cursor under the status block so other messages don't land on the board
+        let summary_lines = if show_summary { SUMMARY_LINES } else { 0 };
+        self.move_cursor(BOARD_HEIGHT + STATUS_LINES + summary_lines, 0)
+    }
+
+    // How the game went: final score, length, level reached, time and steps,
+    // then a bar for each score_history sample (the last bar is the most recent)
+    // Only sent when the status changes, so once as the game ends (and on a full redraw)
+    fn draw_summary(&mut self, game: &GameState) -> Result<(), TxStalled> {
+        self.send_string(b"\x1b[K\r\n\r\nFinal score: ")?;
+        self.send_number(game.score)?;
+        self.send_string(b"   Length: ")?;
+        self.send_number(game.snake_length() as u32)?;
+        self.send_string(b"   Level: ")?;
+        self.send_number(game.level as u32)?;
+        self.send_string(b"   Time: ")?;
+        self.send_time(game.elapsed_secs())?;
+        self.send_string(b"   Steps: ")?;
+        self.send_number(game.ticks_elapsed)?;
+
+        if game.score_history().len() == 0 {
+            return self.send_string(b"\r\nToo short a game to graph the score.");
+        }
+
+        // The best score reaches the top row, and any points at all show as one row
+        let top = game.score_history().max().unwrap_or(0).max(1);
+        self.send_string(b"\r\nScore over time (top of the graph is ")?;
+        self.send_number(top)?;
+        self.send_string(b"):\r\n")?;
+        for row in (1..=GRAPH_ROWS).rev() {
+            self.send_byte(b'|')?;
+            if self.color_enabled {
+                self.send_color(COLOR_GREEN)?;
+            }
+            for score in game.score_history() {
+                let bar = (score * GRAPH_ROWS).div_ceil(top);
+                self.send_byte(if bar >= row { b'#' } else { b' ' })?;
+            }
+            if self.color_enabled {
+                self.send_color(COLOR_RESET)?;
+            }
+            self.send_string(b"\r\n")?;
+        }
+        self.send_byte(b'+')?;
+        for _ in game.score_history() {
+            self.send_byte(b'-')?;
+        }
+        Ok(())
+    }
+}